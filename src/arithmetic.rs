@@ -0,0 +1,470 @@
+use crate::errors::{ShellError, ShellResult};
+use crate::variables;
+
+/// Maximum depth when a variable's value is itself evaluated as an expression
+const MAX_RECURSION: usize = 64;
+
+/// Binary operator precedence levels, loosest first (below `&&`)
+const BINARY_LEVELS: &[&[&str]] = &[
+    &["|"],
+    &["^"],
+    &["&"],
+    &["==", "!="],
+    &["<=", ">=", "<", ">"],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+/// Operators, longest first so that e.g. `<<=` wins over `<<` and `<`
+const OPERATORS: &[&str] = &[
+    "<<=", ">>=", "**", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+=", "-=",
+    "*=", "/=", "%=", "&=", "^=", "|=", "+", "-", "*", "/", "%", "<", ">", "=", "!", "~", "&", "^",
+    "|", "?", ":", ",", "(", ")",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Op(&'static str),
+}
+
+/// Evaluate an arithmetic expression using 64-bit signed integers
+pub fn evaluate(expr: &str) -> ShellResult<i64> {
+    evaluate_nested(expr, 0)
+}
+
+fn evaluate_nested(expr: &str, depth: usize) -> ShellResult<i64> {
+    if depth > MAX_RECURSION {
+        return Err(error(expr, "expression recursion level exceeded"));
+    }
+
+    let tokens = tokenize(expr)?;
+    if tokens.is_empty() {
+        return Ok(0);
+    }
+
+    let mut evaluator = Evaluator {
+        expr,
+        tokens,
+        pos: 0,
+        depth,
+    };
+    let value = evaluator.comma(false)?;
+    match evaluator.peek() {
+        None => Ok(value),
+        Some(token) => Err(error(
+            expr,
+            &format!(
+                "syntax error in expression (error token is \"{}\")",
+                token_text(token)
+            ),
+        )),
+    }
+}
+
+fn error(expr: &str, message: &str) -> ShellError {
+    ShellError::ArithmeticError(format!("{}: {}", expr.trim(), message))
+}
+
+fn token_text(token: &Token) -> String {
+    match token {
+        Token::Number(n) => n.to_string(),
+        Token::Name(name) => name.clone(),
+        Token::Op(op) => op.to_string(),
+    }
+}
+
+fn tokenize(expr: &str) -> ShellResult<Vec<Token>> {
+    let chars: Vec<char> = expr.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_ascii_alphanumeric() || matches!(chars[i], '#' | '_' | '@'))
+            {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            tokens.push(Token::Number(parse_number(expr, &text)?));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Name(chars[start..i].iter().collect()));
+        } else {
+            let rest: String = chars[i..].iter().collect();
+            match OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+                Some(op) => {
+                    tokens.push(Token::Op(op));
+                    i += op.chars().count();
+                }
+                None => {
+                    return Err(error(
+                        expr,
+                        &format!(
+                            "syntax error: invalid arithmetic operator (error token is \"{}\")",
+                            rest
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Parse an integer constant: decimal, `0x` hex, leading-zero octal or `base#digits`
+fn parse_number(expr: &str, text: &str) -> ShellResult<i64> {
+    let invalid = || {
+        error(
+            expr,
+            &format!("value too great for base (error token is \"{}\")", text),
+        )
+    };
+
+    let (base, digits) = if let Some((base, digits)) = text.split_once('#') {
+        let base: u32 = base.parse().map_err(|_| invalid())?;
+        if !(2..=64).contains(&base) {
+            return Err(error(
+                expr,
+                &format!("invalid arithmetic base (error token is \"{}\")", text),
+            ));
+        }
+        (base, digits)
+    } else if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        (16, hex)
+    } else if text.len() > 1 && text.starts_with('0') {
+        (8, &text[1..])
+    } else {
+        (10, text)
+    };
+
+    if digits.is_empty() {
+        return Err(invalid());
+    }
+
+    let mut value: i64 = 0;
+    for c in digits.chars() {
+        // Digits beyond 9 are a-z, A-Z, @ and _ (letters are case-insensitive up to base 36)
+        let digit = match c {
+            '0'..='9' => c as u32 - '0' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 10,
+            'A'..='Z' if base <= 36 => c as u32 - 'A' as u32 + 10,
+            'A'..='Z' => c as u32 - 'A' as u32 + 36,
+            '@' => 62,
+            '_' => 63,
+            _ => return Err(invalid()),
+        };
+        if digit >= base {
+            return Err(invalid());
+        }
+        value = value.wrapping_mul(base as i64).wrapping_add(digit as i64);
+    }
+
+    Ok(value)
+}
+
+/// Recursive-descent evaluator. When `skip` is set (the unevaluated side of
+/// `&&`, `||` or `?:`) the expression is parsed but has no side effects
+struct Evaluator<'a> {
+    expr: &'a str,
+    tokens: Vec<Token>,
+    pos: usize,
+    depth: usize,
+}
+
+impl Evaluator<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.peek() {
+            Some(Token::Op(op)) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn expect_op(&mut self, op: &str) -> ShellResult<()> {
+        if self.peek_op() == Some(op) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.syntax_error())
+        }
+    }
+
+    fn syntax_error(&self) -> ShellError {
+        match self.peek() {
+            Some(token) => error(
+                self.expr,
+                &format!(
+                    "syntax error in expression (error token is \"{}\")",
+                    token_text(token)
+                ),
+            ),
+            None => error(self.expr, "syntax error: operand expected"),
+        }
+    }
+
+    /// Value of a variable; its contents are themselves evaluated as an expression
+    fn variable(&self, name: &str) -> ShellResult<i64> {
+        match variables::get_var(name) {
+            Some(value) if !value.trim().is_empty() => evaluate_nested(&value, self.depth + 1),
+            _ => Ok(0),
+        }
+    }
+
+    fn assign(&self, name: &str, value: i64, skip: bool) {
+        if !skip {
+            variables::set_var(name, &value.to_string());
+        }
+    }
+
+    /// expr , expr
+    fn comma(&mut self, skip: bool) -> ShellResult<i64> {
+        let mut value = self.assignment(skip)?;
+        while self.peek_op() == Some(",") {
+            self.pos += 1;
+            value = self.assignment(skip)?;
+        }
+        Ok(value)
+    }
+
+    /// name = expr, name += expr, ...
+    fn assignment(&mut self, skip: bool) -> ShellResult<i64> {
+        if let (Some(Token::Name(name)), Some(Token::Op(op))) =
+            (self.tokens.get(self.pos), self.tokens.get(self.pos + 1))
+        {
+            if op.ends_with('=') && !matches!(*op, "==" | "!=" | "<=" | ">=") {
+                let (name, op) = (name.clone(), *op);
+                self.pos += 2;
+                let rhs = self.assignment(skip)?;
+                let value = if op == "=" {
+                    rhs
+                } else {
+                    let current = self.variable(&name)?;
+                    self.apply_binary(&op[..op.len() - 1], current, rhs, skip)?
+                };
+                self.assign(&name, value, skip);
+                return Ok(value);
+            }
+        }
+        self.ternary(skip)
+    }
+
+    /// cond ? expr : expr
+    fn ternary(&mut self, skip: bool) -> ShellResult<i64> {
+        let condition = self.logical_or(skip)?;
+        if self.peek_op() != Some("?") {
+            return Ok(condition);
+        }
+        self.pos += 1;
+        let then_value = self.assignment(skip || condition == 0)?;
+        self.expect_op(":")?;
+        let else_value = self.assignment(skip || condition != 0)?;
+        Ok(if condition != 0 {
+            then_value
+        } else {
+            else_value
+        })
+    }
+
+    fn logical_or(&mut self, skip: bool) -> ShellResult<i64> {
+        let mut value = self.logical_and(skip)?;
+        while self.peek_op() == Some("||") {
+            self.pos += 1;
+            let rhs = self.logical_and(skip || value != 0)?;
+            value = (value != 0 || rhs != 0) as i64;
+        }
+        Ok(value)
+    }
+
+    fn logical_and(&mut self, skip: bool) -> ShellResult<i64> {
+        let mut value = self.binary(0, skip)?;
+        while self.peek_op() == Some("&&") {
+            self.pos += 1;
+            let rhs = self.binary(0, skip || value == 0)?;
+            value = (value != 0 && rhs != 0) as i64;
+        }
+        Ok(value)
+    }
+
+    /// Left-associative binary operators, one precedence level at a time
+    fn binary(&mut self, level: usize, skip: bool) -> ShellResult<i64> {
+        if level == BINARY_LEVELS.len() {
+            return self.power(skip);
+        }
+
+        let mut value = self.binary(level + 1, skip)?;
+        while let Some(op) = self
+            .peek_op()
+            .filter(|op| BINARY_LEVELS[level].contains(op))
+        {
+            self.pos += 1;
+            let rhs = self.binary(level + 1, skip)?;
+            value = self.apply_binary(op, value, rhs, skip)?;
+        }
+        Ok(value)
+    }
+
+    fn apply_binary(&self, op: &str, lhs: i64, rhs: i64, skip: bool) -> ShellResult<i64> {
+        Ok(match op {
+            "+" => lhs.wrapping_add(rhs),
+            "-" => lhs.wrapping_sub(rhs),
+            "*" => lhs.wrapping_mul(rhs),
+            "/" | "%" if rhs == 0 => {
+                if skip {
+                    return Ok(0);
+                }
+                return Err(error(self.expr, "division by 0"));
+            }
+            "/" => lhs.wrapping_div(rhs),
+            "%" => lhs.wrapping_rem(rhs),
+            "<<" => lhs.wrapping_shl(rhs as u32),
+            ">>" => lhs.wrapping_shr(rhs as u32),
+            "<" => (lhs < rhs) as i64,
+            ">" => (lhs > rhs) as i64,
+            "<=" => (lhs <= rhs) as i64,
+            ">=" => (lhs >= rhs) as i64,
+            "==" => (lhs == rhs) as i64,
+            "!=" => (lhs != rhs) as i64,
+            "&" => lhs & rhs,
+            "^" => lhs ^ rhs,
+            "|" => lhs | rhs,
+            _ => return Err(self.syntax_error()),
+        })
+    }
+
+    /// base ** exponent (right associative)
+    fn power(&mut self, skip: bool) -> ShellResult<i64> {
+        let base = self.unary(skip)?;
+        if self.peek_op() != Some("**") {
+            return Ok(base);
+        }
+        self.pos += 1;
+        let exponent = self.power(skip)?;
+        if exponent < 0 {
+            if skip {
+                return Ok(0);
+            }
+            return Err(error(self.expr, "exponent less than 0"));
+        }
+        Ok(base.wrapping_pow(exponent.min(u32::MAX as i64) as u32))
+    }
+
+    fn unary(&mut self, skip: bool) -> ShellResult<i64> {
+        match self.peek_op() {
+            Some("-") => {
+                self.pos += 1;
+                Ok(self.unary(skip)?.wrapping_neg())
+            }
+            Some("+") => {
+                self.pos += 1;
+                self.unary(skip)
+            }
+            Some("!") => {
+                self.pos += 1;
+                Ok((self.unary(skip)? == 0) as i64)
+            }
+            Some("~") => {
+                self.pos += 1;
+                Ok(!self.unary(skip)?)
+            }
+            Some(op @ ("++" | "--")) => {
+                self.pos += 1;
+                let name = match self.peek() {
+                    Some(Token::Name(name)) => name.clone(),
+                    _ => return Err(self.syntax_error()),
+                };
+                self.pos += 1;
+                let delta = if op == "++" { 1 } else { -1 };
+                let value = self.variable(&name)?.wrapping_add(delta);
+                self.assign(&name, value, skip);
+                Ok(value)
+            }
+            _ => self.primary(skip),
+        }
+    }
+
+    fn primary(&mut self, skip: bool) -> ShellResult<i64> {
+        match self.peek().cloned() {
+            Some(Token::Number(n)) => {
+                self.pos += 1;
+                Ok(n)
+            }
+            Some(Token::Name(name)) => {
+                self.pos += 1;
+                let value = self.variable(&name)?;
+                // Postfix increment/decrement yields the old value
+                if let Some(op @ ("++" | "--")) = self.peek_op() {
+                    self.pos += 1;
+                    let delta = if op == "++" { 1 } else { -1 };
+                    self.assign(&name, value.wrapping_add(delta), skip);
+                }
+                Ok(value)
+            }
+            Some(Token::Op("(")) => {
+                self.pos += 1;
+                let value = self.comma(skip)?;
+                self.expect_op(")")?;
+                Ok(value)
+            }
+            _ => Err(self.syntax_error()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn precedence_and_associativity() {
+        assert_eq!(evaluate("2 + 3 * 4 ** 2").unwrap(), 50);
+        assert_eq!(evaluate("(1 + 2) * 3").unwrap(), 9);
+        assert_eq!(evaluate("7 - 2 - 1").unwrap(), 4);
+        assert_eq!(evaluate("2 ** 3 ** 2").unwrap(), 512);
+        assert_eq!(evaluate("1 << 2 + 1").unwrap(), 8);
+        assert_eq!(evaluate("-2 ** 2").unwrap(), 4);
+        assert_eq!(evaluate("1 + 2 == 3 && 4 > 3").unwrap(), 1);
+        assert_eq!(evaluate("0 ? 1 : 2 ? 3 : 4").unwrap(), 3);
+    }
+
+    #[test]
+    fn division_by_zero_is_an_error() {
+        assert!(evaluate("1 / 0").is_err());
+        assert!(evaluate("5 % 0").is_err());
+        // The skipped branch isn't evaluated
+        assert_eq!(evaluate("0 && 1 / 0").unwrap(), 0);
+        assert_eq!(evaluate("1 ? 2 : 1 / 0").unwrap(), 2);
+    }
+
+    #[test]
+    fn short_circuit_skips_assignments() {
+        assert_eq!(evaluate("0 && (arith_test_a = 5)").unwrap(), 0);
+        assert_eq!(variables::get_var("arith_test_a"), None);
+        assert_eq!(evaluate("1 || (arith_test_b = 5)").unwrap(), 1);
+        assert_eq!(variables::get_var("arith_test_b"), None);
+        assert_eq!(evaluate("0 ? (arith_test_c = 1) : 4").unwrap(), 4);
+        assert_eq!(variables::get_var("arith_test_c"), None);
+    }
+
+    #[test]
+    fn variables_and_assignment_operators() {
+        assert_eq!(evaluate("arith_test_d = 6, arith_test_d *= 7").unwrap(), 42);
+        assert_eq!(variables::get_var("arith_test_d").as_deref(), Some("42"));
+        assert_eq!(evaluate("arith_test_d++ + ++arith_test_d").unwrap(), 86);
+        assert_eq!(evaluate("arith_test_unset + 1").unwrap(), 1);
+    }
+}
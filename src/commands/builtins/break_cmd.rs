@@ -0,0 +1,50 @@
+use crate::commands::registry::BuiltinCommand;
+use crate::control::{self, Flow};
+use crate::errors::ShellResult;
+
+pub struct BreakCommand;
+
+impl BuiltinCommand for BreakCommand {
+    fn name(&self) -> &'static str {
+        "break"
+    }
+
+    fn description(&self) -> &'static str {
+        "Exit from a for, while or until loop"
+    }
+
+    fn execute(&self, args: &[String]) -> ShellResult<i32> {
+        Ok(request_loop_exit("break", args, Flow::Break))
+    }
+}
+
+/// Shared implementation of `break [n]` and `continue [n]`
+pub(super) fn request_loop_exit(name: &str, args: &[String], flow: fn(usize) -> Flow) -> i32 {
+    let count = match args.get(1) {
+        None => 1,
+        Some(arg) => match arg.parse::<i64>() {
+            Ok(n) if n >= 1 => n as usize,
+            Ok(_) => {
+                eprintln!("{}: {}: loop count out of range", name, arg);
+                return 1;
+            }
+            Err(_) => {
+                eprintln!("{}: {}: numeric argument required", name, arg);
+                return 1;
+            }
+        },
+    };
+
+    let depth = control::loop_depth();
+    if depth == 0 {
+        eprintln!(
+            "{}: only meaningful in a `for', `while', or `until' loop",
+            name
+        );
+        return 0;
+    }
+
+    // Asking to leave more loops than there are leaves all of them
+    control::set_pending(flow(count.min(depth)));
+    0
+}
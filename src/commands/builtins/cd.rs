@@ -1,5 +1,6 @@
 use crate::commands::registry::BuiltinCommand;
 use crate::errors::{ShellError, ShellResult};
+use crate::variables;
 use std::env;
 
 pub struct CdCommand;
@@ -13,27 +14,23 @@ impl BuiltinCommand for CdCommand {
         "Change current working directory"
     }
 
-    fn execute(&self, args: &[String]) -> ShellResult<()> {
-        let path = args.get(1).map(|s| s.as_str());
-
-        let target = match path {
-            Some(p) if p.starts_with('~') => {
-                // Expand ~ to home directory
-                match env::var("HOME") {
-                    Ok(home) => p.replacen('~', &home, 1),
-                    Err(_) => {
-                        return Err(ShellError::InvalidDirectory(
-                            "HOME environment variable not set".to_string(),
-                        ));
-                    }
+    fn execute(&self, args: &[String]) -> ShellResult<i32> {
+        // Tilde expansion has already happened during word expansion
+        let (target, print) = match args.get(1).map(|s| s.as_str()) {
+            Some("-") => match variables::get_var("OLDPWD") {
+                Some(dir) => (dir, true),
+                None => {
+                    return Err(ShellError::InvalidDirectory(
+                        "cd: OLDPWD not set".to_string(),
+                    ));
                 }
-            }
-            Some(p) => p.to_string(),
+            },
+            Some(p) => (p.to_string(), false),
             None => {
                 // No argument provided - go to HOME directory
-                match env::var("HOME") {
-                    Ok(home) => home,
-                    Err(_) => {
+                match variables::get_var("HOME") {
+                    Some(home) => (home, false),
+                    None => {
                         return Err(ShellError::InvalidDirectory(
                             "HOME environment variable not set".to_string(),
                         ));
@@ -42,8 +39,26 @@ impl BuiltinCommand for CdCommand {
             }
         };
 
+        let previous = variables::get_var("PWD").or_else(|| {
+            env::current_dir()
+                .ok()
+                .map(|dir| dir.to_string_lossy().into_owned())
+        });
+
         env::set_current_dir(&target).map_err(|_| {
             ShellError::InvalidDirectory(format!("cd: {}: No such file or directory", target))
-        })
+        })?;
+
+        if let Some(previous) = previous {
+            variables::set_var("OLDPWD", &previous);
+        }
+        if let Ok(current) = env::current_dir() {
+            let current = current.to_string_lossy().into_owned();
+            if print {
+                println!("{}", current);
+            }
+            variables::set_var("PWD", &current);
+        }
+        Ok(0)
     }
 }
@@ -0,0 +1,20 @@
+use super::break_cmd::request_loop_exit;
+use crate::commands::registry::BuiltinCommand;
+use crate::control::Flow;
+use crate::errors::ShellResult;
+
+pub struct ContinueCommand;
+
+impl BuiltinCommand for ContinueCommand {
+    fn name(&self) -> &'static str {
+        "continue"
+    }
+
+    fn description(&self) -> &'static str {
+        "Resume the next iteration of a for, while or until loop"
+    }
+
+    fn execute(&self, args: &[String]) -> ShellResult<i32> {
+        Ok(request_loop_exit("continue", args, Flow::Continue))
+    }
+}
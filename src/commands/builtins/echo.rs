@@ -12,9 +12,9 @@ impl BuiltinCommand for EchoCommand {
         "Echo arguments to stdout"
     }
 
-    fn execute(&self, args: &[String]) -> ShellResult<()> {
+    fn execute(&self, args: &[String]) -> ShellResult<i32> {
         // args[0] is "echo", actual args start at [1]
         println!("{}", args[1..].join(" "));
-        Ok(())
+        Ok(0)
     }
 }
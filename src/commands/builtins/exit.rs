@@ -1,5 +1,6 @@
 use crate::commands::registry::BuiltinCommand;
 use crate::errors::ShellResult;
+use crate::status;
 
 pub struct ExitCommand;
 
@@ -12,13 +13,17 @@ impl BuiltinCommand for ExitCommand {
         "Exit the shell"
     }
 
-    fn execute(&self, _args: &[String]) -> ShellResult<()> {
+    fn execute(&self, _args: &[String]) -> ShellResult<i32> {
         // Exit is handled via exit_code(), this won't normally be called
-        Ok(())
+        Ok(0)
     }
 
     fn exit_code(&self, args: &[String]) -> Option<i32> {
-        let code = args.get(1).and_then(|s| s.parse().ok()).unwrap_or(0);
+        // Without an argument, exit with the status of the last command
+        let code = args
+            .get(1)
+            .and_then(|s| s.parse().ok())
+            .unwrap_or_else(status::last_status);
         Some(code)
     }
 }
@@ -0,0 +1,78 @@
+use crate::commands::registry::BuiltinCommand;
+use crate::errors::ShellResult;
+use crate::variables::{self, is_valid_name};
+
+pub struct ExportCommand;
+
+impl BuiltinCommand for ExportCommand {
+    fn name(&self) -> &'static str {
+        "export"
+    }
+
+    fn description(&self) -> &'static str {
+        "Mark variables for export to child processes"
+    }
+
+    fn execute(&self, args: &[String]) -> ShellResult<i32> {
+        let mut operands = &args[1..];
+
+        // -n removes the export attribute instead of adding it
+        let mut exported = true;
+        while let Some(flag) = operands.first() {
+            match flag.as_str() {
+                "-n" => exported = false,
+                "-p" => {}
+                "--" => {
+                    operands = &operands[1..];
+                    break;
+                }
+                _ => break,
+            }
+            operands = &operands[1..];
+        }
+
+        // No names: list exported variables
+        if operands.is_empty() {
+            for (name, value) in variables::exported_vars() {
+                println!("export {}=\"{}\"", name, escape_value(&value));
+            }
+            for name in variables::pending_exports() {
+                println!("export {}", name);
+            }
+            return Ok(0);
+        }
+
+        let mut status = 0;
+        for operand in operands {
+            let (name, value) = match operand.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (operand.as_str(), None),
+            };
+
+            if !is_valid_name(name) {
+                eprintln!("export: `{}': not a valid identifier", operand);
+                status = 1;
+                continue;
+            }
+
+            if let Some(value) = value {
+                variables::set_var(name, value);
+            }
+            variables::set_exported(name, exported);
+        }
+
+        Ok(status)
+    }
+}
+
+/// Escape a value for display inside double quotes
+fn escape_value(value: &str) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | '$' | '`') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
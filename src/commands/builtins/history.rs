@@ -1,6 +1,13 @@
 use crate::commands::registry::BuiltinCommand;
-use crate::errors::{ShellError, ShellResult};
-use crate::history;
+use crate::errors::ShellResult;
+use crate::history::{self, HistoryEntry};
+use crate::variables;
+use std::ffi::CString;
+use std::io;
+use std::path::Path;
+
+/// Time format of `history -l` when `HISTTIMEFORMAT` is unset
+const DEFAULT_TIME_FORMAT: &str = "%F %T";
 
 pub struct HistoryCommand;
 
@@ -10,22 +17,367 @@ impl BuiltinCommand for HistoryCommand {
     }
 
     fn description(&self) -> &'static str {
-        "Display or read command history"
+        "Display or manipulate command history"
     }
 
-    fn execute(&self, args: &[String]) -> ShellResult<()> {
-        if args.len() >= 3 && args[1] == "-r" {
-            // Load history from file (silently)
-            let file_path = &args[2];
-            history::load_history_from_file(file_path).map_err(ShellError::IoError)?;
-        } else {
-            // Display history
-            let entries = history::get_history();
-            for (i, entry) in entries.iter().enumerate() {
-                println!("{:>5}  {}", i + 1, entry);
+    fn execute(&self, args: &[String]) -> ShellResult<i32> {
+        let option = args.get(1).map(|s| s.as_str());
+        let operand = args.get(2).map(|s| s.as_str());
+
+        match option {
+            Some("-c") => {
+                history::clear_history();
+                Ok(0)
+            }
+            Some("-d") => match operand {
+                Some(offset) => Ok(delete_entry(offset)),
+                None => {
+                    eprintln!("history: -d: option requires an argument");
+                    Ok(2)
+                }
+            },
+            // File operations default to $HISTFILE
+            Some("-r") => Ok(with_file(operand, history::load_history_from_file)),
+            Some("-w") => Ok(with_file(operand, history::write_to_file)),
+            Some("-a") => Ok(with_file(operand, history::append_to_file)),
+            Some("-n") => Ok(with_file(operand, history::read_new_from_file)),
+            _ => match ListOptions::parse(&args[1..]) {
+                Ok(options) => Ok(print_history(&options)),
+                Err(status) => Ok(status),
+            },
+        }
+    }
+}
+
+/// Which exit statuses `history -e`/`-F` lists
+enum StatusFilter {
+    Exact(i32),
+    Failed,
+}
+
+/// Options for listing the history:
+/// `history [-lF] [-D dir] [-e status] [-S time] [-U time] [n]`
+#[derive(Default)]
+struct ListOptions {
+    /// `-l`: show start time, duration, status and directory
+    long: bool,
+    /// `-D dir`: commands run in `dir` or below it
+    dir: Option<String>,
+    /// `-e status` or `-F`
+    status: Option<StatusFilter>,
+    /// `-S time`: commands started at or after `time`
+    since: Option<u64>,
+    /// `-U time`: commands started before `time`
+    until: Option<u64>,
+    /// Only the last `n` matching entries
+    count: Option<usize>,
+}
+
+impl ListOptions {
+    /// Parse listing options, printing a message and returning the exit
+    /// status on error
+    fn parse(args: &[String]) -> Result<Self, i32> {
+        let mut options = Self::default();
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-l" => options.long = true,
+                "-F" => options.status = Some(StatusFilter::Failed),
+                "-D" | "-e" | "-S" | "-U" => {
+                    let Some(value) = args.next() else {
+                        eprintln!("history: {}: option requires an argument", arg);
+                        return Err(2);
+                    };
+                    match arg.as_str() {
+                        "-D" => options.dir = Some(absolute_dir(value)),
+                        "-e" => match value.parse() {
+                            Ok(status) => options.status = Some(StatusFilter::Exact(status)),
+                            Err(_) => {
+                                eprintln!("history: {}: numeric argument required", value);
+                                return Err(1);
+                            }
+                        },
+                        _ => {
+                            let Some(time) = parse_time(value) else {
+                                eprintln!("history: {}: invalid time specification", value);
+                                return Err(1);
+                            };
+                            if arg == "-S" {
+                                options.since = Some(time);
+                            } else {
+                                options.until = Some(time);
+                            }
+                        }
+                    }
+                }
+                _ if arg.starts_with('-') && arg.len() > 1 => {
+                    eprintln!("history: {}: invalid option", arg);
+                    eprintln!(
+                        "history: usage: history [-c] [-d offset] [-lF] [-D dir] [-e status] \
+                         [-S time] [-U time] [n] or history -anrw [filename]"
+                    );
+                    return Err(2);
+                }
+                _ => match arg.parse() {
+                    Ok(count) => options.count = Some(count),
+                    Err(_) => {
+                        eprintln!("history: {}: numeric argument required", arg);
+                        return Err(1);
+                    }
+                },
             }
         }
+        Ok(options)
+    }
+
+    /// Whether an entry passes the filters. Entries without the metadata a
+    /// filter needs never do
+    fn matches(&self, entry: &HistoryEntry) -> bool {
+        if let Some(dir) = &self.dir {
+            let Some(cwd) = &entry.cwd else {
+                return false;
+            };
+            if !Path::new(cwd).starts_with(dir) {
+                return false;
+            }
+        }
+        match (&self.status, entry.status) {
+            (None, _) => {}
+            (Some(StatusFilter::Exact(wanted)), Some(status)) if status == *wanted => {}
+            (Some(StatusFilter::Failed), Some(status)) if status != 0 => {}
+            _ => return false,
+        }
+        if self.since.is_some() || self.until.is_some() {
+            let Some(started) = entry.started else {
+                return false;
+            };
+            if self.since.is_some_and(|since| started < since)
+                || self.until.is_some_and(|until| started >= until)
+            {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Print the entries matching `options` with their entry numbers
+fn print_history(options: &ListOptions) -> i32 {
+    let time_format = variables::get_var("HISTTIMEFORMAT");
+    let entries: Vec<(usize, HistoryEntry)> = history::get_entries()
+        .into_iter()
+        .enumerate()
+        .filter(|(_, entry)| options.matches(entry))
+        .collect();
+    let skip = options
+        .count
+        .map_or(0, |count| entries.len().saturating_sub(count));
+
+    let entries = &entries[skip..];
+
+    // Unknown times are shorter than the others, so pad the column
+    let long_format = time_format.as_deref().unwrap_or(DEFAULT_TIME_FORMAT);
+    let time_width = entries
+        .iter()
+        .map(|(_, entry)| entry_time(entry, long_format).chars().count())
+        .max()
+        .unwrap_or(0);
+
+    for (i, entry) in entries {
+        if options.long {
+            println!(
+                "{:>5}  {:<time_width$}  {:>8}  {:>3}  {}  {}",
+                i + 1,
+                entry_time(entry, long_format),
+                entry.duration.map_or("-".to_string(), format_duration),
+                entry.status.map_or("-".to_string(), |s| s.to_string()),
+                entry.cwd.as_deref().map_or("-".to_string(), home_relative),
+                entry.command
+            );
+        } else if let Some(format) = &time_format {
+            println!(
+                "{:>5}  {}{}",
+                i + 1,
+                entry_time(entry, format),
+                entry.command
+            );
+        } else {
+            println!("{:>5}  {}", i + 1, entry.command);
+        }
+    }
+    0
+}
+
+/// An entry's start time in `format`, or `??` if it isn't known
+fn entry_time(entry: &HistoryEntry, format: &str) -> String {
+    entry
+        .started
+        .map_or("??".to_string(), |time| format_time(time, format))
+}
+
+/// Format a duration in milliseconds as `850ms`, `1.2s`, `3m05s` or `1h02m`
+fn format_duration(ms: u64) -> String {
+    let secs = ms / 1000;
+    if ms < 1000 {
+        format!("{}ms", ms)
+    } else if secs < 60 {
+        format!("{:.1}s", ms as f64 / 1000.0)
+    } else if secs < 3600 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}h{:02}m", secs / 3600, secs % 3600 / 60)
+    }
+}
+
+/// Abbreviate a directory under `$HOME` with `~`. A `HOME` of `/` is left
+/// alone, or every directory would be abbreviated
+fn home_relative(dir: &str) -> String {
+    let home = variables::get_var("HOME").unwrap_or_default();
+    let home = home.trim_end_matches('/');
+    // Path::starts_with compares whole components: `/home/ab` isn't under `/home/a`
+    if home.is_empty() || !Path::new(dir).starts_with(home) {
+        return dir.to_string();
+    }
+    format!("~{}", &dir[home.len()..])
+}
+
+/// Resolve a `-D` directory against the current directory
+fn absolute_dir(dir: &str) -> String {
+    let path = std::env::current_dir().unwrap_or_default().join(dir);
+    path.canonicalize()
+        .unwrap_or(path)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Format a Unix time as local time with a `strftime` format
+fn format_time(time: u64, format: &str) -> String {
+    let Ok(format) = CString::new(format) else {
+        return String::new();
+    };
+    let time = time as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    let mut buf = [0u8; 256];
+    let len = unsafe {
+        libc::localtime_r(&time, &mut tm);
+        libc::strftime(
+            buf.as_mut_ptr() as *mut libc::c_char,
+            buf.len(),
+            format.as_ptr(),
+            &tm,
+        )
+    };
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+/// Parse a `-S`/`-U` time: `now`, `today`, `yesterday`, `@<unix time>`,
+/// an age such as `30m`, `2h`, `3d` or `1w`, or a local
+/// `YYYY-MM-DD[ HH:MM[:SS]]` date
+fn parse_time(text: &str) -> Option<u64> {
+    let now = history::unix_time();
+    match text {
+        "now" => return Some(now),
+        "today" => return local_midnight(now, 0),
+        "yesterday" => return local_midnight(now, -1),
+        _ => {}
+    }
+    if let Some(time) = text.strip_prefix('@') {
+        return time.parse().ok();
+    }
+
+    let unit = match text.chars().last()? {
+        's' => Some(1),
+        'm' => Some(60),
+        'h' => Some(3600),
+        'd' => Some(86400),
+        'w' => Some(7 * 86400),
+        _ => None,
+    };
+    if let Some(unit) = unit {
+        let count: u64 = text[..text.len() - 1].parse().ok()?;
+        return now.checked_sub(count.checked_mul(unit)?);
+    }
+
+    let (date, clock) = text.split_once([' ', 'T']).unwrap_or((text, "0:0"));
+    let date: Vec<i32> = date
+        .split('-')
+        .map(|n| n.parse().ok())
+        .collect::<Option<_>>()?;
+    let clock: Vec<i32> = clock
+        .split(':')
+        .map(|n| n.parse().ok())
+        .collect::<Option<_>>()?;
+    let ([year, month, day], [hour, minute, second @ ..]) = (date.as_slice(), clock.as_slice())
+    else {
+        return None;
+    };
+    if second.len() > 1 {
+        return None;
+    }
+
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    tm.tm_year = year - 1900;
+    tm.tm_mon = month - 1;
+    tm.tm_mday = *day;
+    tm.tm_hour = *hour;
+    tm.tm_min = *minute;
+    tm.tm_sec = second.first().copied().unwrap_or(0);
+    make_time(&mut tm)
+}
+
+/// Local midnight `days` days from the day of `time`
+fn local_midnight(time: u64, days: i32) -> Option<u64> {
+    let time = time as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe { libc::localtime_r(&time, &mut tm) };
+    tm.tm_mday += days;
+    tm.tm_hour = 0;
+    tm.tm_min = 0;
+    tm.tm_sec = 0;
+    make_time(&mut tm)
+}
+
+/// Convert a broken-down local time to a Unix time
+fn make_time(tm: &mut libc::tm) -> Option<u64> {
+    // Let mktime work out whether daylight saving time applies
+    tm.tm_isdst = -1;
+    let time = unsafe { libc::mktime(tm) };
+    u64::try_from(time).ok()
+}
+
+/// Delete the entry at a 1-based offset; negative offsets count back from
+/// the end (`-1` is the last entry)
+fn delete_entry(offset: &str) -> i32 {
+    let len = history::get_history().len() as i64;
+    let index = match offset.parse::<i64>() {
+        Ok(n) if n > 0 => n - 1,
+        Ok(n) if n < 0 => len + n,
+        _ => -1,
+    };
+
+    if index < 0 || !history::delete_entry(index as usize) {
+        eprintln!("history: {}: history position out of range", offset);
+        return 1;
+    }
+    0
+}
+
+/// Run a history file operation on `file`, or on `$HISTFILE` if not given
+fn with_file(file: Option<&str>, operation: fn(&str) -> io::Result<()>) -> i32 {
+    let path = match file.map(String::from).or_else(history::history_file) {
+        Some(path) => path,
+        None => {
+            eprintln!("history: HISTFILE is not set");
+            return 1;
+        }
+    };
 
-        Ok(())
+    match operation(&path) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("history: {}: {}", path, e);
+            1
+        }
     }
 }
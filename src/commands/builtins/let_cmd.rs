@@ -0,0 +1,31 @@
+use crate::arithmetic;
+use crate::commands::registry::BuiltinCommand;
+use crate::errors::{ShellError, ShellResult};
+
+pub struct LetCommand;
+
+impl BuiltinCommand for LetCommand {
+    fn name(&self) -> &'static str {
+        "let"
+    }
+
+    fn description(&self) -> &'static str {
+        "Evaluate arithmetic expressions"
+    }
+
+    fn execute(&self, args: &[String]) -> ShellResult<i32> {
+        if args.len() < 2 {
+            return Err(ShellError::ArithmeticError(
+                "let: expression expected".to_string(),
+            ));
+        }
+
+        // Each argument is a separate expression; the last one decides the status
+        let mut value = 0;
+        for expr in &args[1..] {
+            value = arithmetic::evaluate(expr)?;
+        }
+
+        Ok(if value != 0 { 0 } else { 1 })
+    }
+}
@@ -0,0 +1,45 @@
+use crate::commands::registry::BuiltinCommand;
+use crate::errors::ShellResult;
+use crate::functions;
+use crate::variables::{self, is_valid_name};
+
+pub struct LocalCommand;
+
+impl BuiltinCommand for LocalCommand {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    fn description(&self) -> &'static str {
+        "Define variables local to a function"
+    }
+
+    fn execute(&self, args: &[String]) -> ShellResult<i32> {
+        if !functions::in_function() {
+            eprintln!("local: can only be used in a function");
+            return Ok(1);
+        }
+
+        let mut status = 0;
+        for operand in &args[1..] {
+            let (name, value) = match operand.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (operand.as_str(), None),
+            };
+
+            if !is_valid_name(name) {
+                eprintln!("local: `{}': not a valid identifier", operand);
+                status = 1;
+                continue;
+            }
+
+            // The previous value comes back when the function returns
+            variables::make_local(name);
+            if let Some(value) = value {
+                variables::set_var(name, value);
+            }
+        }
+
+        Ok(status)
+    }
+}
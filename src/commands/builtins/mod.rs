@@ -4,6 +4,15 @@ mod cd;
 mod type_cmd;
 mod exit;
 mod history;
+mod export;
+mod unset;
+mod let_cmd;
+mod set;
+mod shopt;
+mod break_cmd;
+mod continue_cmd;
+mod local;
+mod return_cmd;
 
 pub use echo::EchoCommand;
 pub use pwd::PwdCommand;
@@ -11,3 +20,12 @@ pub use cd::CdCommand;
 pub use type_cmd::TypeCommand;
 pub use exit::ExitCommand;
 pub use history::HistoryCommand;
+pub use export::ExportCommand;
+pub use unset::UnsetCommand;
+pub use let_cmd::LetCommand;
+pub use set::SetCommand;
+pub use shopt::ShoptCommand;
+pub use break_cmd::BreakCommand;
+pub use continue_cmd::ContinueCommand;
+pub use local::LocalCommand;
+pub use return_cmd::ReturnCommand;
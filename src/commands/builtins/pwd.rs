@@ -13,9 +13,9 @@ impl BuiltinCommand for PwdCommand {
         "Print current working directory"
     }
 
-    fn execute(&self, _args: &[String]) -> ShellResult<()> {
+    fn execute(&self, _args: &[String]) -> ShellResult<i32> {
         let current_dir = env::current_dir().map_err(ShellError::IoError)?;
         println!("{}", current_dir.display());
-        Ok(())
+        Ok(0)
     }
 }
@@ -0,0 +1,39 @@
+use crate::commands::registry::BuiltinCommand;
+use crate::control::{self, Flow};
+use crate::errors::ShellResult;
+use crate::functions;
+use crate::status;
+
+pub struct ReturnCommand;
+
+impl BuiltinCommand for ReturnCommand {
+    fn name(&self) -> &'static str {
+        "return"
+    }
+
+    fn description(&self) -> &'static str {
+        "Return from a shell function"
+    }
+
+    fn execute(&self, args: &[String]) -> ShellResult<i32> {
+        if !functions::in_function() {
+            eprintln!("return: can only `return' from a function or sourced script");
+            return Ok(1);
+        }
+
+        // Without an argument, return the status of the last command
+        let status = match args.get(1) {
+            None => status::last_status(),
+            Some(arg) => match arg.parse::<i64>() {
+                Ok(n) => (n & 0xff) as i32,
+                Err(_) => {
+                    eprintln!("return: {}: numeric argument required", arg);
+                    2
+                }
+            },
+        };
+
+        control::set_pending(Flow::Return(status));
+        Ok(status)
+    }
+}
@@ -0,0 +1,78 @@
+use crate::commands::registry::BuiltinCommand;
+use crate::errors::ShellResult;
+use crate::options::{self, OptionKind};
+use crate::variables;
+
+pub struct SetCommand;
+
+impl BuiltinCommand for SetCommand {
+    fn name(&self) -> &'static str {
+        "set"
+    }
+
+    fn description(&self) -> &'static str {
+        "Set shell options or list shell variables"
+    }
+
+    fn execute(&self, args: &[String]) -> ShellResult<i32> {
+        if args.len() == 1 {
+            for (name, value) in variables::all_vars() {
+                println!("{}={}", name, quote_value(&value));
+            }
+            return Ok(0);
+        }
+
+        let mut i = 1;
+        while i < args.len() {
+            let arg = args[i].as_str();
+            let enable = arg.starts_with('-');
+
+            match arg {
+                "-o" | "+o" => match args.get(i + 1) {
+                    Some(name) => {
+                        if !options::set_enabled(name, OptionKind::Set, enable) {
+                            eprintln!("set: {}: invalid option name", name);
+                            return Ok(1);
+                        }
+                        i += 1;
+                    }
+                    None => list_options(enable),
+                },
+                // Single-letter forms of the long options
+                "-C" | "+C" => {
+                    options::set_enabled("noclobber", OptionKind::Set, enable);
+                }
+                _ => {
+                    eprintln!("set: {}: invalid option", arg);
+                    return Ok(2);
+                }
+            }
+            i += 1;
+        }
+
+        Ok(0)
+    }
+}
+
+/// `set -o` lists the options with their state, `set +o` as commands
+fn list_options(human: bool) {
+    for (name, enabled) in options::list_options(OptionKind::Set) {
+        if human {
+            println!("{:<15}\t{}", name, if enabled { "on" } else { "off" });
+        } else {
+            println!("set {}o {}", if enabled { "-" } else { "+" }, name);
+        }
+    }
+}
+
+/// Quote a value so it can be read back by the shell
+fn quote_value(value: &str) -> String {
+    let safe = value
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "_-./:,+@%=".contains(c));
+    if safe && !value.is_empty() {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
+}
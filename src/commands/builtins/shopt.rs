@@ -0,0 +1,72 @@
+use crate::commands::registry::BuiltinCommand;
+use crate::errors::ShellResult;
+use crate::options::{self, OptionKind};
+
+pub struct ShoptCommand;
+
+impl BuiltinCommand for ShoptCommand {
+    fn name(&self) -> &'static str {
+        "shopt"
+    }
+
+    fn description(&self) -> &'static str {
+        "Set and unset shell options"
+    }
+
+    fn execute(&self, args: &[String]) -> ShellResult<i32> {
+        let mut set = None;
+        let mut quiet = false;
+        let mut reusable = false;
+        let mut names = Vec::new();
+
+        for arg in &args[1..] {
+            match arg.as_str() {
+                "-s" => set = Some(true),
+                "-u" => set = Some(false),
+                "-q" => quiet = true,
+                "-p" => reusable = true,
+                _ => names.push(arg.as_str()),
+            }
+        }
+
+        for name in &names {
+            if !options::is_option(name, OptionKind::Shopt) {
+                eprintln!("shopt: {}: invalid shell option name", name);
+                return Ok(1);
+            }
+        }
+
+        // -s / -u change the named options
+        if let Some(enabled) = set {
+            for name in &names {
+                options::set_enabled(name, OptionKind::Shopt, enabled);
+            }
+            if !names.is_empty() {
+                return Ok(0);
+            }
+        }
+
+        // Otherwise report options; the status says whether all listed ones are on
+        let mut status = 0;
+        for (name, enabled) in options::list_options(OptionKind::Shopt) {
+            let listed = names.contains(&name);
+            let selected = listed || (names.is_empty() && set.map_or(true, |s| s == enabled));
+            if !selected {
+                continue;
+            }
+            if listed && !enabled {
+                status = 1;
+            }
+            if quiet {
+                continue;
+            }
+            if reusable {
+                println!("shopt {} {}", if enabled { "-s" } else { "-u" }, name);
+            } else {
+                println!("{:<15}\t{}", name, if enabled { "on" } else { "off" });
+            }
+        }
+
+        Ok(status)
+    }
+}
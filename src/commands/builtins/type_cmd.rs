@@ -1,6 +1,8 @@
 use crate::commands::registry::{BuiltinCommand, BUILTINS};
 use crate::errors::ShellResult;
-use std::env;
+use crate::functions;
+use crate::parser::printer::function_source;
+use crate::variables;
 use std::path::Path;
 
 pub struct TypeCommand;
@@ -14,17 +16,23 @@ impl BuiltinCommand for TypeCommand {
         "Print the type of a command"
     }
 
-    fn execute(&self, args: &[String]) -> ShellResult<()> {
+    fn execute(&self, args: &[String]) -> ShellResult<i32> {
         let name = args.get(1).map(|s| s.as_str()).unwrap_or("");
 
+        if let Some(def) = functions::get_function(name) {
+            println!("{} is a function", name);
+            println!("{}", function_source(&def));
+            return Ok(0);
+        }
+
         // Check builtins using the registry
         if BUILTINS.is_builtin(name) {
             println!("{} is a shell builtin", name);
-            return Ok(());
+            return Ok(0);
         }
 
         // Check PATH
-        if let Ok(path) = env::var("PATH") {
+        if let Some(path) = variables::get_var("PATH") {
             for dir in path.split(':') {
                 let full_path = format!("{}/{}", dir, name);
                 let path = Path::new(&full_path);
@@ -34,7 +42,7 @@ impl BuiltinCommand for TypeCommand {
                         let is_executable = metadata.permissions().mode() & 0o111 != 0;
                         if metadata.is_file() && is_executable {
                             println!("{} is {}", name, full_path);
-                            return Ok(());
+                            return Ok(0);
                         }
                     }
                 }
@@ -42,6 +50,6 @@ impl BuiltinCommand for TypeCommand {
         }
 
         println!("{}: not found", name);
-        Ok(())
+        Ok(1)
     }
 }
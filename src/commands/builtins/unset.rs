@@ -0,0 +1,51 @@
+use crate::commands::registry::BuiltinCommand;
+use crate::errors::ShellResult;
+use crate::functions;
+use crate::variables::{self, is_valid_name};
+
+pub struct UnsetCommand;
+
+impl BuiltinCommand for UnsetCommand {
+    fn name(&self) -> &'static str {
+        "unset"
+    }
+
+    fn description(&self) -> &'static str {
+        "Remove shell variables or functions"
+    }
+
+    fn execute(&self, args: &[String]) -> ShellResult<i32> {
+        let mut status = 0;
+        // -f removes functions instead of variables
+        let mut remove_functions = false;
+
+        for name in &args[1..] {
+            match name.as_str() {
+                "-v" => {
+                    remove_functions = false;
+                    continue;
+                }
+                "-f" => {
+                    remove_functions = true;
+                    continue;
+                }
+                _ => {}
+            }
+
+            if remove_functions {
+                functions::remove_function(name);
+                continue;
+            }
+
+            if !is_valid_name(name) {
+                eprintln!("unset: `{}': not a valid identifier", name);
+                status = 1;
+                continue;
+            }
+
+            variables::unset_var(name);
+        }
+
+        Ok(status)
+    }
+}
@@ -1,111 +1,98 @@
 use crate::errors::{ShellError, ShellResult};
-use crate::redirection::{parse_redirection, setup_redirection};
-use std::env;
-use std::path::Path;
-use std::process::Command;
-
-/// Execute an external command
-pub fn run_external_command(args: &[String]) -> ShellResult<()> {
+use crate::redirection::{setup_redirection, Redirections};
+use crate::variables;
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::process::{Command, ExitStatus};
+
+/// Execute an external command, returning its exit status
+pub fn run_external_command(args: &[String], redirections: &Redirections) -> ShellResult<i32> {
     if args.is_empty() {
-        return Ok(());
+        return Ok(0);
     }
 
     let command_name = &args[0];
+    let command_path = find_command_path(command_name)?;
 
-    // Parse redirection operators
-    let (filtered_args, stdout_redir, stderr_redir) = parse_redirection(args)?;
-
-    // If command contains '/', execute directly
-    if command_name.contains('/') {
-        return execute_command_direct(command_name, &filtered_args[1..], &stdout_redir, &stderr_redir);
-    }
-
-    // Try to execute as a simple command first
-    match execute_command_simple(&filtered_args[0], &filtered_args[1..], &stdout_redir, &stderr_redir) {
-        Ok(_) => return Ok(()),
-        Err(ShellError::CommandNotFound(_)) => {
-            // Only fall back to PATH search if command wasn't found
-            execute_command_from_path(command_name, &filtered_args[1..], &stdout_redir, &stderr_redir)
-        }
-        Err(e) => Err(e), // Command was found but failed, don't retry
-    }
-}
-
-/// Execute a command directly (with absolute or relative path)
-fn execute_command_direct(
-    command_path: &str,
-    args: &[String],
-    stdout_redir: &Option<crate::redirection::Redirection>,
-    stderr_redir: &Option<crate::redirection::Redirection>,
-) -> ShellResult<()> {
-    let mut cmd = Command::new(command_path);
-    cmd.args(args);
-
-    setup_redirection(&mut cmd, stdout_redir, stderr_redir)?;
-
-    cmd.status().map_err(|e| {
-        ShellError::ExecutionError(format!("Failed to execute {}: {}", command_path, e))
-    })?;
-
-    // Non-zero exit status is normal, not an error
-    Ok(())
-}
+    let mut cmd = Command::new(&command_path);
+    cmd.arg0(command_name);
+    cmd.args(&args[1..]);
 
-/// Execute a command without PATH search
-fn execute_command_simple(
-    command_name: &str,
-    args: &[String],
-    stdout_redir: &Option<crate::redirection::Redirection>,
-    stderr_redir: &Option<crate::redirection::Redirection>,
-) -> ShellResult<()> {
-    let mut cmd = Command::new(command_name);
-    cmd.args(args);
+    setup_environment(&mut cmd);
+    setup_redirection(&mut cmd, redirections)?;
 
-    setup_redirection(&mut cmd, stdout_redir, stderr_redir)?;
-
-    cmd.status()
-        .map_err(|_| ShellError::CommandNotFound(command_name.to_string()))?;
+    let status = cmd.status().map_err(|e| spawn_error(command_name, e))?;
 
     // Non-zero exit status is normal, not an error
-    Ok(())
+    Ok(exit_code(status))
 }
 
-/// Execute a command by searching PATH directories
-fn execute_command_from_path(
-    command_name: &str,
-    args: &[String],
-    stdout_redir: &Option<crate::redirection::Redirection>,
-    stderr_redir: &Option<crate::redirection::Redirection>,
-) -> ShellResult<()> {
-    let path_var = env::var("PATH").map_err(|_| {
-        ShellError::CommandNotFound(format!("{}: command not found", command_name))
-    })?;
+/// Find the file to run for a command: a name containing `/` is used as it
+/// is, anything else is searched for in `PATH`. As in bash, an executable
+/// file wins over an earlier one that isn't executable
+pub fn find_command_path(command_name: &str) -> ShellResult<String> {
+    if command_name.contains('/') {
+        return Ok(command_name.to_string());
+    }
 
+    let path_var = variables::get_var("PATH").unwrap_or_default();
+    let mut not_executable = None;
     for path_dir in path_var.split(':') {
+        // An empty PATH entry means the current directory
+        let path_dir = if path_dir.is_empty() { "." } else { path_dir };
         let full_path = format!("{}/{}", path_dir, command_name);
-        if Path::new(&full_path).exists() {
-            let mut cmd = Command::new(&full_path);
-            cmd.args(args);
-
-            setup_redirection(&mut cmd, stdout_redir, stderr_redir)?;
-
-            match cmd.status() {
-                Ok(_) => {
-                    // Non-zero exit status is normal, not an error
-                    return Ok(());
-                }
-                Err(e) => {
-                    return Err(ShellError::ExecutionError(format!(
-                        "Failed to execute {}: {}",
-                        full_path, e
-                    )));
+        match fs::metadata(&full_path) {
+            Ok(metadata) if metadata.is_file() => {
+                if metadata.permissions().mode() & 0o111 != 0 {
+                    return Ok(full_path);
                 }
+                not_executable.get_or_insert(full_path);
             }
+            _ => {}
+        }
+    }
+
+    // Running a file that isn't executable reports "Permission denied"
+    not_executable
+        .ok_or_else(|| ShellError::CommandNotFound(format!("{}: command not found", command_name)))
+}
+
+/// Convert a process exit status into a shell status code
+/// Processes killed by a signal report 128 + the signal number
+pub fn exit_code(status: ExitStatus) -> i32 {
+    match status.code() {
+        Some(code) => code,
+        None => 128 + status.signal().unwrap_or(0),
+    }
+}
+
+/// Map a failure to start a process onto the matching shell error: 127 if
+/// there is no such file, 126 if it can't be executed
+pub fn spawn_error(command_name: &str, e: io::Error) -> ShellError {
+    if e.raw_os_error() == Some(libc::ENOEXEC) {
+        return ShellError::PermissionDenied(format!(
+            "{}: cannot execute binary file: Exec format error",
+            command_name
+        ));
+    }
+    match e.kind() {
+        io::ErrorKind::NotFound if command_name.contains('/') => {
+            ShellError::CommandNotFound(format!("{}: No such file or directory", command_name))
+        }
+        io::ErrorKind::NotFound => {
+            ShellError::CommandNotFound(format!("{}: command not found", command_name))
         }
+        io::ErrorKind::PermissionDenied => {
+            ShellError::PermissionDenied(format!("{}: Permission denied", command_name))
+        }
+        _ => ShellError::ExecutionError(format!("Failed to execute {}: {}", command_name, e)),
     }
+}
 
-    Err(ShellError::CommandNotFound(format!(
-        "{}: command not found",
-        command_name
-    )))
+/// Give a child process the shell's exported variables as its environment
+pub fn setup_environment(cmd: &mut Command) {
+    cmd.env_clear();
+    cmd.envs(variables::exported_vars());
 }
@@ -6,25 +6,38 @@ pub use external::run_external_command;
 pub use registry::BUILTINS;
 
 use crate::errors::ShellResult;
+use crate::functions;
+use crate::history;
+use crate::redirection::{setup_builtin_redirection, Redirections};
 
-/// Execute a command (checks builtins first, then external)
-pub fn handle_command(args: &[String]) -> ShellResult<()> {
+/// Execute a command (checks functions first, then builtins, then external)
+/// Returns the command's exit status
+pub fn handle_command(args: &[String], redirections: &Redirections) -> ShellResult<i32> {
     if args.is_empty() {
-        return Ok(());
+        return Ok(0);
     }
 
     let cmd_name = &args[0];
 
+    if let Some(function) = functions::get_function(cmd_name) {
+        let _guard = setup_builtin_redirection(redirections)?;
+        return functions::call_function(&function, args);
+    }
+
     // Check for exit command first
     if let Some(code) = BUILTINS.check_exit(cmd_name, args) {
+        history::save_on_exit();
         std::process::exit(code);
     }
 
-    // Try builtin
-    if let Some(result) = BUILTINS.execute(cmd_name, args) {
-        return result;
+    // Try builtin (redirections are restored when the guard is dropped)
+    if BUILTINS.is_builtin(cmd_name) {
+        let _guard = setup_builtin_redirection(redirections)?;
+        if let Some(result) = BUILTINS.execute(cmd_name, args) {
+            return result;
+        }
     }
 
     // Fall back to external command
-    run_external_command(args)
+    run_external_command(args, redirections)
 }
@@ -11,7 +11,8 @@ pub trait BuiltinCommand: Send + Sync {
 
     /// Execute the command with the given arguments
     /// args[0] is the command name itself
-    fn execute(&self, args: &[String]) -> ShellResult<()>;
+    /// Returns the exit status of the command (0 for success)
+    fn execute(&self, args: &[String]) -> ShellResult<i32>;
 
     /// Whether this command should cause the shell to exit
     /// Returns Some(exit_code) if shell should exit, None otherwise
@@ -21,6 +22,7 @@ pub trait BuiltinCommand: Send + Sync {
 }
 
 /// Central registry for all builtin commands
+#[derive(Default)]
 pub struct BuiltinRegistry {
     commands: Vec<Box<dyn BuiltinCommand>>,
 }
@@ -47,7 +49,7 @@ impl BuiltinRegistry {
     }
 
     /// Execute a builtin command by name
-    pub fn execute(&self, name: &str, args: &[String]) -> Option<ShellResult<()>> {
+    pub fn execute(&self, name: &str, args: &[String]) -> Option<ShellResult<i32>> {
         self.commands
             .iter()
             .find(|c| c.name() == name)
@@ -74,6 +76,15 @@ pub static BUILTINS: Lazy<BuiltinRegistry> = Lazy::new(|| {
     registry.register(super::builtins::TypeCommand);
     registry.register(super::builtins::ExitCommand);
     registry.register(super::builtins::HistoryCommand);
+    registry.register(super::builtins::ExportCommand);
+    registry.register(super::builtins::UnsetCommand);
+    registry.register(super::builtins::LetCommand);
+    registry.register(super::builtins::ShoptCommand);
+    registry.register(super::builtins::SetCommand);
+    registry.register(super::builtins::BreakCommand);
+    registry.register(super::builtins::ContinueCommand);
+    registry.register(super::builtins::LocalCommand);
+    registry.register(super::builtins::ReturnCommand);
 
     registry
 });
@@ -1,10 +1,11 @@
 use crate::commands::BUILTINS;
+use crate::parser::is_incomplete;
+use crate::variables;
 use rustyline::completion::{Completer, Pair};
 use rustyline::hint::Hinter;
 use rustyline::highlight::{Highlighter, CmdKind};
-use rustyline::validate::Validator;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
 use rustyline::{Context, Helper};
-use std::env;
 use std::fs;
 
 /// Shell completer for tab completion of commands
@@ -25,7 +26,7 @@ impl Completer for ShellCompleter {
 
         // Only complete the first word (command name)
         if parts.len() <= 1 && !input.ends_with(' ') {
-            let prefix = parts.first().map(|s| *s).unwrap_or("");
+            let prefix = parts.first().copied().unwrap_or("");
             let mut candidates = Vec::new();
 
             // Add builtin commands from registry
@@ -39,7 +40,7 @@ impl Completer for ShellCompleter {
             }
 
             // Add executables from PATH
-            if let Ok(path_var) = env::var("PATH") {
+            if let Some(path_var) = variables::get_var("PATH") {
                 for path_dir in path_var.split(':') {
                     if let Ok(entries) = fs::read_dir(path_dir) {
                         for entry in entries.flatten() {
@@ -121,6 +122,18 @@ impl Highlighter for ShellCompleter {
     }
 }
 
-impl Validator for ShellCompleter {}
+impl Validator for ShellCompleter {
+    /// A single line the parser can't finish is accepted, so the REPL can ask
+    /// for the rest under the `PS2` prompt (rustyline has no prompt for
+    /// continuation rows). Text pasted over several lines is held until it
+    /// forms a complete command
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        let input = ctx.input();
+        if input.contains('\n') && is_incomplete(input) {
+            return Ok(ValidationResult::Incomplete);
+        }
+        Ok(ValidationResult::Valid(None))
+    }
+}
 
 impl Helper for ShellCompleter {}
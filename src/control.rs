@@ -0,0 +1,92 @@
+use once_cell::sync::Lazy;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// A request to leave the normal flow of execution
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Flow {
+    /// `break n`: leave the `n` innermost loops
+    Break(usize),
+    /// `continue n`: leave `n - 1` loops and start the next iteration of the last one
+    Continue(usize),
+    /// `return`: leave the running function with this status
+    Return(i32),
+}
+
+/// Control flow requested by `break`/`continue`/`return` that hasn't been handled yet
+static PENDING: Lazy<Mutex<Option<Flow>>> = Lazy::new(|| Mutex::new(None));
+
+/// Number of loops currently executing
+static LOOP_DEPTH: AtomicUsize = AtomicUsize::new(0);
+
+/// Request a change of control flow; lists stop running until a loop handles it
+pub fn set_pending(flow: Flow) {
+    if let Ok(mut pending) = PENDING.lock() {
+        *pending = Some(flow);
+    }
+}
+
+/// Whether a `break`, `continue` or `return` is waiting to be handled
+pub fn is_pending() -> bool {
+    PENDING.lock().map(|p| p.is_some()).unwrap_or(false)
+}
+
+fn take_pending() -> Option<Flow> {
+    PENDING.lock().ok().and_then(|mut p| p.take())
+}
+
+/// Take a pending `return`, leaving any other request in place
+pub fn take_return() -> Option<i32> {
+    let mut pending = PENDING.lock().ok()?;
+    match *pending {
+        Some(Flow::Return(status)) => {
+            *pending = None;
+            Some(status)
+        }
+        _ => None,
+    }
+}
+
+/// Number of enclosing loops
+pub fn loop_depth() -> usize {
+    LOOP_DEPTH.load(Ordering::SeqCst)
+}
+
+/// Guard that counts a loop as running until it is dropped
+pub struct LoopGuard;
+
+impl LoopGuard {
+    pub fn enter() -> Self {
+        LOOP_DEPTH.fetch_add(1, Ordering::SeqCst);
+        LoopGuard
+    }
+
+    /// Handle a pending `break`/`continue` after running part of the loop.
+    /// Returns true if the loop should stop
+    pub fn should_exit(&self) -> bool {
+        match take_pending() {
+            None | Some(Flow::Continue(1)) => false,
+            Some(Flow::Break(1)) => true,
+            // The rest is left for the enclosing loops
+            Some(Flow::Break(n)) => {
+                set_pending(Flow::Break(n - 1));
+                true
+            }
+            Some(Flow::Continue(n)) => {
+                set_pending(Flow::Continue(n - 1));
+                true
+            }
+            // `return` leaves every loop of the function
+            Some(flow @ Flow::Return(_)) => {
+                set_pending(flow);
+                true
+            }
+        }
+    }
+}
+
+impl Drop for LoopGuard {
+    fn drop(&mut self) {
+        LOOP_DEPTH.fetch_sub(1, Ordering::SeqCst);
+    }
+}
@@ -19,14 +19,55 @@ pub enum ShellError {
     #[error("IO error: {0}")]
     IoError(#[from] io::Error),
 
+    #[error("Permission denied: {0}")]
+    PermissionDenied(String),
+
     #[error("Invalid directory: {0}")]
     InvalidDirectory(String),
 
-    #[error("Invalid quoting in command")]
-    InvalidQuoting,
+    #[error("{0}")]
+    ExpansionError(String),
+
+    #[error("{0}")]
+    ArithmeticError(String),
+
+    #[error("{0}")]
+    SyntaxError(String),
+
+    /// The input ends in the middle of a construct that needs more lines
+    #[error("{0}")]
+    IncompleteInput(String),
 
     #[error("Interrupted")]
     Interrupted,
 }
 
+impl ShellError {
+    /// Exit status reported in `$?` when a command fails with this error
+    pub fn exit_status(&self) -> i32 {
+        match self {
+            ShellError::CommandNotFound(_) => 127,
+            ShellError::PermissionDenied(_) => 126,
+            ShellError::SyntaxError(_) | ShellError::IncompleteInput(_) => 2,
+            _ => 1,
+        }
+    }
+}
+
 pub type ShellResult<T> = Result<T, ShellError>;
+
+/// Print an error to stderr in the format the shell uses for failed commands
+pub fn report_error(e: &ShellError) {
+    match e {
+        ShellError::CommandNotFound(msg) => eprintln!("{}", msg),
+        ShellError::PermissionDenied(msg) => eprintln!("{}", msg),
+        ShellError::InvalidDirectory(msg) => eprintln!("{}", msg),
+        ShellError::ExecutionError(msg) => eprintln!("{}", msg),
+        ShellError::RedirectionError(msg) => eprintln!("{}", msg),
+        ShellError::SyntaxError(msg) => eprintln!("{}", msg),
+        ShellError::IncompleteInput(msg) => eprintln!("{}", msg),
+        ShellError::ExpansionError(msg) => eprintln!("{}", msg),
+        ShellError::ArithmeticError(msg) => eprintln!("{}", msg),
+        _ => eprintln!("Error: {}", e),
+    }
+}
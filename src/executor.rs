@@ -0,0 +1,311 @@
+use crate::commands::handle_command;
+use crate::control::{self, LoopGuard};
+use crate::errors::{report_error, ShellResult};
+use crate::expansion::pattern::Pattern;
+use crate::expansion::{
+    expand_arithmetic, expand_assignment_value, expand_pattern, expand_word, expand_words,
+    process_substitution_mark, reap_process_substitutions,
+};
+use crate::functions;
+use crate::parser::ast::{
+    AndOr, AndOrOp, Assignment, CaseItem, CaseTerminator, Command, CompoundCommand, List, Pipeline,
+    Redirect, SimpleCommand, Word,
+};
+use crate::pipeline::execute_pipeline;
+use crate::process::{fork_shell, wait_for_child};
+use crate::redirection::{resolve_redirections, setup_builtin_redirection};
+use crate::status;
+use crate::variables::{self, Variable};
+
+/// Execute every AND-OR list of a command list in order
+/// Returns the exit status of the last command run (0 for an empty list)
+pub fn execute_list(list: &List) -> ShellResult<i32> {
+    if list.items.is_empty() {
+        return Ok(0);
+    }
+    for and_or in &list.items {
+        execute_and_or(and_or);
+        // `break`/`continue` skip the rest of the list
+        if control::is_pending() {
+            break;
+        }
+    }
+    Ok(status::last_status())
+}
+
+/// Execute an AND-OR list, skipping pipelines whose connector doesn't match the status
+fn execute_and_or(and_or: &AndOr) {
+    run_pipeline(&and_or.first);
+
+    for (op, pipeline) in &and_or.rest {
+        if control::is_pending() {
+            return;
+        }
+        let should_run = match op {
+            AndOrOp::And => status::last_status() == 0,
+            AndOrOp::Or => status::last_status() != 0,
+        };
+        if should_run {
+            run_pipeline(pipeline);
+        }
+    }
+}
+
+/// Run a pipeline and record its exit status, reporting any error
+fn run_pipeline(pipeline: &Pipeline) {
+    let status = match execute_pipeline_node(pipeline) {
+        Ok(status) => status,
+        Err(e) => {
+            report_error(&e);
+            e.exit_status()
+        }
+    };
+    status::set_last_status(status);
+}
+
+/// Execute a single pipeline node
+fn execute_pipeline_node(pipeline: &Pipeline) -> ShellResult<i32> {
+    match pipeline.commands.as_slice() {
+        [command] => execute_command(command),
+        stages => execute_pipeline(stages),
+    }
+}
+
+/// Execute a single command in the current shell
+pub(crate) fn execute_command(command: &Command) -> ShellResult<i32> {
+    // Process substitutions live as long as the command using them
+    let mark = process_substitution_mark();
+    let result = match command {
+        Command::Simple(simple) => execute_simple_command(simple),
+        Command::Arithmetic(expr) => {
+            expand_arithmetic(expr).map(|value| if value != 0 { 0 } else { 1 })
+        }
+        Command::Compound(compound, redirects) => execute_compound(compound, redirects),
+        Command::Function(def) => {
+            functions::define_function(def.clone());
+            Ok(0)
+        }
+    };
+    reap_process_substitutions(mark);
+    result
+}
+
+/// Execute a compound command with its redirections applied to the whole body
+pub(crate) fn execute_compound(
+    compound: &CompoundCommand,
+    redirects: &[Redirect],
+) -> ShellResult<i32> {
+    let redirections = resolve_redirections(redirects)?;
+
+    if let CompoundCommand::Subshell(body) = compound {
+        // Changes to the directory, variables and options stay in the child
+        let pid = fork_shell(|| {
+            let _guard = match setup_builtin_redirection(&redirections) {
+                Ok(guard) => guard,
+                Err(e) => {
+                    report_error(&e);
+                    return e.exit_status();
+                }
+            };
+            execute_list(body).unwrap_or(1)
+        })?;
+        return Ok(wait_for_child(pid));
+    }
+
+    let _guard = setup_builtin_redirection(&redirections)?;
+    match compound {
+        CompoundCommand::Subshell(body) | CompoundCommand::Group(body) => execute_list(body),
+        CompoundCommand::If {
+            branches,
+            else_body,
+        } => execute_if(branches, else_body.as_ref()),
+        CompoundCommand::While {
+            condition,
+            body,
+            until,
+        } => execute_while(condition, body, *until),
+        CompoundCommand::For { name, words, body } => execute_for(name, words.as_deref(), body),
+        CompoundCommand::ArithFor {
+            init,
+            condition,
+            step,
+            body,
+        } => execute_arith_for(init, condition, step, body),
+        CompoundCommand::Case { word, items } => execute_case(word, items),
+    }
+}
+
+/// Run the body of the first branch whose condition succeeds, or the `else` body
+fn execute_if(branches: &[(List, List)], else_body: Option<&List>) -> ShellResult<i32> {
+    for (condition, body) in branches {
+        let status = execute_list(condition)?;
+        if control::is_pending() {
+            return Ok(status);
+        }
+        if status == 0 {
+            return execute_list(body);
+        }
+    }
+    match else_body {
+        Some(body) => execute_list(body),
+        None => Ok(0),
+    }
+}
+
+/// Run the body while the condition succeeds (or, for `until`, fails)
+fn execute_while(condition: &List, body: &List, until: bool) -> ShellResult<i32> {
+    let looping = LoopGuard::enter();
+    let mut status = 0;
+
+    loop {
+        let result = execute_list(condition)?;
+        if looping.should_exit() || (result == 0) == until {
+            break;
+        }
+        status = execute_list(body)?;
+        if looping.should_exit() {
+            break;
+        }
+    }
+
+    Ok(status)
+}
+
+/// Run the body once for each expanded word with `name` set to it
+fn execute_for(name: &str, words: Option<&[Word]>, body: &List) -> ShellResult<i32> {
+    let values = match words {
+        Some(words) => expand_words(words)?,
+        // Without `in`, the loop runs over the positional parameters
+        None => variables::positional_params(),
+    };
+
+    let looping = LoopGuard::enter();
+    let mut status = 0;
+
+    for value in values {
+        variables::set_var(name, &value);
+        status = execute_list(body)?;
+        if looping.should_exit() {
+            break;
+        }
+    }
+
+    Ok(status)
+}
+
+/// Run a C-style `for (( init; condition; step ))` loop. An empty
+/// condition counts as true
+fn execute_arith_for(init: &str, condition: &str, step: &str, body: &List) -> ShellResult<i32> {
+    if !init.trim().is_empty() {
+        expand_arithmetic(init)?;
+    }
+
+    let looping = LoopGuard::enter();
+    let mut status = 0;
+
+    while condition.trim().is_empty() || expand_arithmetic(condition)? != 0 {
+        status = execute_list(body)?;
+        if looping.should_exit() {
+            break;
+        }
+        if !step.trim().is_empty() {
+            expand_arithmetic(step)?;
+        }
+    }
+
+    Ok(status)
+}
+
+/// Run the items of a `case` command whose patterns match the expanded word
+fn execute_case(word: &Word, items: &[CaseItem]) -> ShellResult<i32> {
+    let subject = expand_word(word)?;
+    let mut status = 0;
+    let mut fall_through = false;
+
+    for item in items {
+        if !fall_through && !case_item_matches(item, &subject)? {
+            continue;
+        }
+        status = execute_list(&item.body)?;
+        if control::is_pending() {
+            break;
+        }
+        match item.terminator {
+            CaseTerminator::Break => break,
+            CaseTerminator::FallThrough => fall_through = true,
+            CaseTerminator::Continue => fall_through = false,
+        }
+    }
+
+    Ok(status)
+}
+
+/// Whether any of the item's patterns matches the `case` word
+fn case_item_matches(item: &CaseItem, subject: &str) -> ShellResult<bool> {
+    for pattern in &item.patterns {
+        if Pattern::new(&expand_pattern(pattern.as_str())?).matches(subject) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Expand and run a simple command in the current shell
+fn execute_simple_command(command: &SimpleCommand) -> ShellResult<i32> {
+    let args = expand_words(&command.words)?;
+    let redirections = resolve_redirections(&command.redirects)?;
+
+    if args.is_empty() {
+        // Assignments without a command set shell variables
+        status::take_substitution_status();
+        for assignment in &command.assignments {
+            let value = expand_assignment_value(&assignment.value)?;
+            variables::set_var(&assignment.name, &value);
+        }
+
+        // Redirections without a command still create/truncate their files
+        let _guard = setup_builtin_redirection(&redirections)?;
+
+        // The status is that of the last command substitution, if any
+        return Ok(status::take_substitution_status().unwrap_or(0));
+    }
+
+    let _assignments = apply_prefix_assignments(&command.assignments)?;
+    handle_command(&args, &redirections)
+}
+
+/// Guard that restores variables overridden by `NAME=value` prefixes when dropped
+pub struct AssignmentGuard {
+    saved: Vec<(String, Option<Variable>)>,
+}
+
+impl Drop for AssignmentGuard {
+    fn drop(&mut self) {
+        for (name, previous) in self.saved.drain(..).rev() {
+            variables::set_variable(&name, previous);
+        }
+    }
+}
+
+/// Apply assignments preceding a command as exported variables that only
+/// last while it runs. Returns a guard that restores the previous values
+pub fn apply_prefix_assignments(assignments: &[Assignment]) -> ShellResult<AssignmentGuard> {
+    let mut guard = AssignmentGuard { saved: Vec::new() };
+
+    for assignment in assignments {
+        let value = expand_assignment_value(&assignment.value)?;
+        guard.saved.push((
+            assignment.name.clone(),
+            variables::get_variable(&assignment.name),
+        ));
+        variables::set_variable(
+            &assignment.name,
+            Some(Variable {
+                value,
+                exported: true,
+            }),
+        );
+    }
+
+    Ok(guard)
+}
@@ -0,0 +1,240 @@
+use super::{find_closing_backquote, find_closing_brace, find_closing_paren};
+
+/// Most items a `{x..y}` sequence may produce; longer ones are left unexpanded
+const MAX_SEQUENCE_ITEMS: u64 = 1_000_000;
+
+/// Perform brace expansion on a raw word, producing one raw word per
+/// alternative. Quoted and escaped braces and `${...}` are left alone
+pub(super) fn expand_braces(word: &str) -> Vec<String> {
+    let chars: Vec<char> = word.chars().collect();
+    let mut i = 0;
+
+    while i < chars.len() {
+        if chars[i] == '{' {
+            if let Some((close, alternatives)) = parse_brace(&chars, i) {
+                let prefix: String = chars[..i].iter().collect();
+                let suffix: String = chars[close + 1..].iter().collect();
+                let suffixes = expand_braces(&suffix);
+
+                let mut words = Vec::new();
+                for alternative in alternatives {
+                    for middle in expand_braces(&alternative) {
+                        for end in &suffixes {
+                            words.push(format!("{}{}{}", prefix, middle, end));
+                        }
+                    }
+                }
+                return words;
+            }
+        }
+        i = skip_construct(&chars, i) + 1;
+    }
+
+    vec![word.to_string()]
+}
+
+/// Return the index of the last character of the quoted string, escape or
+/// substitution starting at `i` (or `i` itself for an ordinary character)
+fn skip_construct(chars: &[char], i: usize) -> usize {
+    let last = chars.len().saturating_sub(1);
+    match chars[i] {
+        '\\' => (i + 1).min(last),
+        '\'' => (i + 1..chars.len())
+            .find(|&j| chars[j] == '\'')
+            .unwrap_or(last),
+        '"' => {
+            let mut j = i + 1;
+            while j < chars.len() && chars[j] != '"' {
+                if chars[j] == '\\' {
+                    j += 1;
+                }
+                j += 1;
+            }
+            j.min(last)
+        }
+        '`' => find_closing_backquote(chars, i + 1).unwrap_or(last),
+        '<' | '>' if chars.get(i + 1) == Some(&'(') => {
+            find_closing_paren(chars, i + 2).unwrap_or(last)
+        }
+        '$' => match chars.get(i + 1) {
+            Some('{') => find_closing_brace(chars, i + 2).unwrap_or(last),
+            Some('(') => find_closing_paren(chars, i + 2).unwrap_or(last),
+            _ => i,
+        },
+        _ => i,
+    }
+}
+
+/// Parse a brace expression opening at `open`. Returns the index of the
+/// closing `}` and the alternatives, or None if it isn't a valid list or sequence
+fn parse_brace(chars: &[char], open: usize) -> Option<(usize, Vec<String>)> {
+    let mut depth = 0;
+    let mut commas = Vec::new();
+    let mut i = open + 1;
+
+    let close = loop {
+        let c = *chars.get(i)?;
+        match c {
+            '{' => depth += 1,
+            '}' if depth == 0 => break i,
+            '}' => depth -= 1,
+            ',' if depth == 0 => commas.push(i),
+            _ => {
+                i = skip_construct(chars, i);
+            }
+        }
+        i += 1;
+    };
+
+    if commas.is_empty() {
+        let content: String = chars[open + 1..close].iter().collect();
+        return expand_sequence(&content).map(|items| (close, items));
+    }
+
+    let mut alternatives = Vec::new();
+    let mut start = open + 1;
+    for end in commas.into_iter().chain([close]) {
+        alternatives.push(chars[start..end].iter().collect());
+        start = end + 1;
+    }
+    Some((close, alternatives))
+}
+
+/// Expand `x..y` or `x..y..step` into its items, for integers (zero-padded
+/// when either end has a leading zero) or single characters
+fn expand_sequence(content: &str) -> Option<Vec<String>> {
+    let parts: Vec<&str> = content.split("..").collect();
+    let (start, end, step) = match parts.as_slice() {
+        [start, end] => (*start, *end, None),
+        [start, end, step] => (*start, *end, Some(step.parse::<i64>().ok()?)),
+        _ => return None,
+    };
+    let step = step.map_or(1, |s| s.unsigned_abs().max(1));
+
+    if let (Ok(first), Ok(last)) = (start.parse::<i64>(), end.parse::<i64>()) {
+        let width = if has_leading_zero(start) || has_leading_zero(end) {
+            start.len().max(end.len())
+        } else {
+            0
+        };
+        return Some(
+            range(first, last, step)?
+                .into_iter()
+                .map(|n| {
+                    if n < 0 {
+                        let n = n.unsigned_abs();
+                        format!("-{:0>width$}", n, width = width.saturating_sub(1))
+                    } else {
+                        format!("{:0>width$}", n, width = width)
+                    }
+                })
+                .collect(),
+        );
+    }
+
+    let (first, last) = (single_char(start)?, single_char(end)?);
+    Some(
+        range(first as i64, last as i64, step)?
+            .into_iter()
+            .filter_map(|n| char::from_u32(n as u32))
+            .map(|c| c.to_string())
+            .collect(),
+    )
+}
+
+/// Numbers from `first` to `last` inclusive, counting up or down by `step`.
+/// None if there would be more than `MAX_SEQUENCE_ITEMS`
+fn range(first: i64, last: i64, step: u64) -> Option<Vec<i64>> {
+    if first.abs_diff(last) / step >= MAX_SEQUENCE_ITEMS {
+        return None;
+    }
+
+    let mut items = Vec::new();
+    let mut n = Some(first);
+    while let Some(current) = n {
+        if (first <= last && current > last) || (first > last && current < last) {
+            break;
+        }
+        items.push(current);
+        // The sequence also ends where the next number would overflow
+        n = if first <= last {
+            current.checked_add_unsigned(step)
+        } else {
+            current.checked_sub_unsigned(step)
+        };
+    }
+    Some(items)
+}
+
+fn has_leading_zero(number: &str) -> bool {
+    let digits = number.strip_prefix('-').unwrap_or(number);
+    digits.len() > 1 && digits.starts_with('0')
+}
+
+/// The character of a one-character ASCII letter endpoint
+fn single_char(text: &str) -> Option<char> {
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_alphabetic() => Some(c),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(word: &str) -> Vec<String> {
+        expand_braces(word)
+    }
+
+    #[test]
+    fn lists() {
+        assert_eq!(expand("a{b,c}d"), ["abd", "acd"]);
+        assert_eq!(expand("{a,b}{1,2}"), ["a1", "a2", "b1", "b2"]);
+        assert_eq!(expand("x{a,{b,c}}y"), ["xay", "xby", "xcy"]);
+        assert_eq!(expand("{,a}"), ["", "a"]);
+    }
+
+    #[test]
+    fn not_expanded() {
+        assert_eq!(expand("{a}"), ["{a}"]);
+        assert_eq!(expand("a{b"), ["a{b"]);
+        assert_eq!(expand("'{a,b}'"), ["'{a,b}'"]);
+        assert_eq!(expand(r"\{a,b}"), [r"\{a,b}"]);
+        assert_eq!(expand("${x,y}"), ["${x,y}"]);
+    }
+
+    #[test]
+    fn sequences() {
+        assert_eq!(expand("{1..4}"), ["1", "2", "3", "4"]);
+        assert_eq!(expand("{3..1}"), ["3", "2", "1"]);
+        assert_eq!(expand("{1..10..3}"), ["1", "4", "7", "10"]);
+        assert_eq!(expand("{05..1..2}"), ["05", "03", "01"]);
+        assert_eq!(expand("{-1..1}"), ["-1", "0", "1"]);
+        assert_eq!(expand("{c..a}"), ["c", "b", "a"]);
+        assert_eq!(expand("{a..c}{1..2}"), ["a1", "a2", "b1", "b2", "c1", "c2"]);
+        assert_eq!(expand("{1..a}"), ["{1..a}"]);
+    }
+
+    #[test]
+    fn sequences_stop_before_overflow() {
+        assert_eq!(
+            expand("{9223372036854775806..9223372036854775807}"),
+            ["9223372036854775806", "9223372036854775807"]
+        );
+        assert_eq!(
+            expand("{-9223372036854775807..-9223372036854775808}"),
+            ["-9223372036854775807", "-9223372036854775808"]
+        );
+        assert_eq!(
+            expand("{0..9223372036854775807..9223372036854775807}"),
+            ["0", "9223372036854775807"]
+        );
+    }
+
+    #[test]
+    fn huge_sequences_are_left_alone() {
+        assert_eq!(expand("{1..999999999999}"), ["{1..999999999999}"]);
+    }
+}
@@ -0,0 +1,130 @@
+use super::pattern::{has_pattern_chars, unescape_pattern, Pattern};
+use crate::options;
+use std::fs;
+use std::path::Path;
+
+/// Expand a pathname pattern into the sorted list of matching paths.
+/// Hidden files only match when the pattern component starts with a literal `.`
+/// (or `dotglob` is set); `**` matches across directories when `globstar` is set
+pub(super) fn glob(pattern: &str) -> Vec<String> {
+    let components: Vec<&str> = pattern.split('/').collect();
+    let mut results = Vec::new();
+
+    match components.split_first() {
+        Some((&"", rest)) => walk("/", rest, &mut results),
+        _ => walk("", &components, &mut results),
+    }
+
+    results.sort();
+    results.dedup();
+    results
+}
+
+/// Join a directory prefix and an entry name
+fn join(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else if prefix.ends_with('/') {
+        format!("{}{}", prefix, name)
+    } else {
+        format!("{}/{}", prefix, name)
+    }
+}
+
+/// Names of the entries of a directory (`""` means the current directory)
+fn read_names(prefix: &str) -> Vec<String> {
+    let dir = if prefix.is_empty() { "." } else { prefix };
+    match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .flatten()
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Match the remaining pattern components below `prefix`
+fn walk(prefix: &str, components: &[&str], results: &mut Vec<String>) {
+    let Some((&component, rest)) = components.split_first() else {
+        results.push(prefix.to_string());
+        return;
+    };
+
+    // A trailing slash only matches directories
+    if component.is_empty() {
+        if rest.is_empty() {
+            if Path::new(prefix).is_dir() {
+                results.push(format!("{}/", prefix.trim_end_matches('/')));
+            }
+        } else {
+            walk(prefix, rest, results);
+        }
+        return;
+    }
+
+    let dotglob = options::is_enabled("dotglob");
+
+    if component == "**" && options::is_enabled("globstar") {
+        walk_recursive(prefix, rest, dotglob, results);
+        return;
+    }
+
+    if !has_pattern_chars(component) {
+        let path = join(prefix, &unescape_pattern(component));
+        if rest.is_empty() {
+            if Path::new(&path).symlink_metadata().is_ok() {
+                results.push(path);
+            }
+        } else if Path::new(&path).is_dir() {
+            walk(&path, rest, results);
+        }
+        return;
+    }
+
+    let pattern = Pattern::new(component);
+    let match_hidden = dotglob || component.starts_with('.');
+
+    for name in read_names(prefix) {
+        if name.starts_with('.') && !match_hidden {
+            continue;
+        }
+        if !pattern.matches(&name) {
+            continue;
+        }
+
+        let path = join(prefix, &name);
+        if rest.is_empty() {
+            results.push(path);
+        } else if Path::new(&path).is_dir() {
+            walk(&path, rest, results);
+        }
+    }
+}
+
+/// `**`: match the rest of the pattern in `prefix` and every directory below it.
+/// As the last component it matches every file and directory recursively
+fn walk_recursive(prefix: &str, rest: &[&str], dotglob: bool, results: &mut Vec<String>) {
+    if !rest.is_empty() {
+        walk(prefix, rest, results);
+    }
+
+    for name in read_names(prefix) {
+        if name.starts_with('.') && !dotglob {
+            continue;
+        }
+
+        let path = join(prefix, &name);
+        let metadata = match fs::symlink_metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+
+        if rest.is_empty() {
+            results.push(path.clone());
+        }
+        // Symlinked directories are not followed, to avoid cycles
+        if metadata.is_dir() {
+            walk_recursive(&path, rest, dotglob, results);
+        }
+    }
+}
@@ -0,0 +1,682 @@
+mod brace;
+mod glob;
+mod parameter;
+pub mod pattern;
+mod substitution;
+mod tilde;
+
+use crate::arithmetic;
+use crate::errors::{ShellError, ShellResult};
+use crate::options;
+use crate::parser::ast::Word;
+use crate::status;
+use crate::variables;
+use brace::expand_braces;
+use parameter::expand_braced;
+use pattern::{escape_pattern, has_pattern_chars, unescape_pattern};
+use substitution::{command_substitution, process_substitution, unescape_backquoted};
+pub use substitution::{
+    inherit_process_substitutions, process_substitution_mark, reap_process_substitutions,
+};
+use tilde::expand_tilde;
+
+/// Characters used for field splitting when `IFS` is unset
+const DEFAULT_IFS: &str = " \t\n";
+
+/// A run of expanded text together with how it was produced
+#[derive(Debug, Clone)]
+struct Segment {
+    text: String,
+    /// Came from inside quotes (or was escaped)
+    quoted: bool,
+    /// Result of an unquoted expansion, subject to field splitting
+    split: bool,
+    /// Special meaning for field splitting, for `"$@"`
+    mark: Option<FieldMark>,
+}
+
+/// Field boundaries produced by `"$@"`
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FieldMark {
+    /// Ends the current field (between two positional parameters)
+    Break,
+    /// `"$@"` without parameters: the field is dropped if it ends up empty
+    Empty,
+}
+
+impl Segment {
+    fn literal(text: String) -> Self {
+        Self {
+            text,
+            quoted: false,
+            split: false,
+            mark: None,
+        }
+    }
+
+    fn quoted(text: String) -> Self {
+        Self {
+            text,
+            quoted: true,
+            split: false,
+            mark: None,
+        }
+    }
+
+    /// The result of an expansion, split into fields unless quoted
+    fn expanded(text: String, quoted: bool) -> Self {
+        Self {
+            text,
+            quoted,
+            split: !quoted,
+            mark: None,
+        }
+    }
+
+    /// A field boundary; the text is what it turns into when the word
+    /// isn't split (a space between parameters)
+    fn boundary(mark: FieldMark) -> Self {
+        let text = match mark {
+            FieldMark::Break => " ",
+            FieldMark::Empty => "",
+        };
+        Self {
+            text: text.to_string(),
+            quoted: true,
+            split: false,
+            mark: Some(mark),
+        }
+    }
+}
+
+/// Expand a list of words into command arguments. Brace expansion runs
+/// first, and each resulting word then goes through the other expansions
+pub fn expand_words(words: &[Word]) -> ShellResult<Vec<String>> {
+    let mut args = Vec::new();
+    for word in words {
+        for raw in expand_braces(word.as_str()) {
+            args.extend(expand_word_fields(&Word(raw))?);
+        }
+    }
+    Ok(args)
+}
+
+/// Expand a word into zero or more fields (parameter expansion,
+/// field splitting, pathname expansion and quote removal)
+pub fn expand_word_fields(word: &Word) -> ShellResult<Vec<String>> {
+    let mut expander = WordExpander::new(word.as_str());
+    // Arguments that look like assignments (`export PATH=~/bin`) get
+    // tilde expansion in their value too
+    if let Some((name, _)) = word.as_str().split_once('=') {
+        if variables::is_valid_name(name) {
+            expander = expander.assignment(name.chars().count() + 1);
+        }
+    }
+    let segments = expander.expand()?;
+    let ifs = variables::get_var("IFS").unwrap_or_else(|| DEFAULT_IFS.to_string());
+
+    let mut fields = Vec::new();
+    for field in split_fields(segments, &ifs) {
+        fields.extend(expand_pathname(field)?);
+    }
+    Ok(fields)
+}
+
+/// Replace a field containing unquoted pattern characters with the
+/// matching pathnames. Without a match the field is kept as is, dropped
+/// (`nullglob`) or reported as an error (`failglob`)
+fn expand_pathname(field: Vec<Segment>) -> ShellResult<Vec<String>> {
+    let pattern: String = field
+        .iter()
+        .map(|s| {
+            if s.quoted {
+                escape_pattern(&s.text)
+            } else {
+                s.text.clone()
+            }
+        })
+        .collect();
+
+    if !has_pattern_chars(&pattern) {
+        return Ok(vec![field.into_iter().map(|s| s.text).collect()]);
+    }
+
+    let matches = glob::glob(&pattern);
+    if !matches.is_empty() {
+        return Ok(matches);
+    }
+
+    if options::is_enabled("failglob") {
+        return Err(ShellError::ExpansionError(format!(
+            "no match: {}",
+            unescape_pattern(&pattern)
+        )));
+    }
+    if options::is_enabled("nullglob") {
+        return Ok(Vec::new());
+    }
+    Ok(vec![field.into_iter().map(|s| s.text).collect()])
+}
+
+/// Expand a word into exactly one string, without field splitting
+/// (used for redirection targets and operator operands)
+pub fn expand_word(word: &Word) -> ShellResult<String> {
+    let segments = WordExpander::new(word.as_str()).expand()?;
+    Ok(segments.into_iter().map(|s| s.text).collect())
+}
+
+/// Expand the body of a here-document with an unquoted delimiter:
+/// parameters, substitutions and backslash escapes, but no quote removal
+pub fn expand_heredoc(body: &str) -> ShellResult<String> {
+    let mut expander = WordExpander::new(body);
+    expander.heredoc = true;
+    expander.expand_double_quoted()?;
+    Ok(expander.segments.into_iter().map(|s| s.text).collect())
+}
+
+/// Expand the value of a `NAME=value` assignment. Like `expand_word`, but
+/// tildes after a `:` are expanded too (`PATH=~/bin:~/.local/bin`)
+pub fn expand_assignment_value(word: &Word) -> ShellResult<String> {
+    let segments = WordExpander::new(word.as_str()).assignment(0).expand()?;
+    Ok(segments.into_iter().map(|s| s.text).collect())
+}
+
+/// Expand a word into a shell pattern: quoted characters are escaped so
+/// they only match themselves, unquoted ones keep their pattern meaning
+pub fn expand_pattern(raw: &str) -> ShellResult<String> {
+    let segments = WordExpander::new(raw).expand()?;
+    Ok(segments
+        .into_iter()
+        .map(|s| {
+            if s.quoted {
+                escape_pattern(&s.text)
+            } else {
+                s.text
+            }
+        })
+        .collect())
+}
+
+/// Expand parameters and command substitutions in an arithmetic expression,
+/// then evaluate it
+pub fn expand_arithmetic(raw: &str) -> ShellResult<i64> {
+    let expr = expand_word(&Word(raw.to_string()))?;
+    arithmetic::evaluate(&expr)
+}
+
+/// Look up a variable or special parameter by name
+fn lookup_parameter(name: &str) -> Option<String> {
+    match name {
+        "?" => Some(status::last_status().to_string()),
+        "$" => Some(std::process::id().to_string()),
+        "0" => Some(
+            std::env::args()
+                .next()
+                .unwrap_or_else(|| "shell".to_string()),
+        ),
+        "#" => Some(variables::positional_params().len().to_string()),
+        "@" => Some(variables::positional_params().join(" ")),
+        // `$*` joins the parameters with the first character of IFS
+        "*" => {
+            let ifs = variables::get_var("IFS").unwrap_or_else(|| DEFAULT_IFS.to_string());
+            let separator = ifs.chars().next().map(String::from).unwrap_or_default();
+            Some(variables::positional_params().join(&separator))
+        }
+        _ if name.chars().all(|c| c.is_ascii_digit()) => {
+            let index: usize = name.parse().ok()?;
+            variables::positional_params()
+                .get(index.checked_sub(1)?)
+                .cloned()
+        }
+        _ => variables::get_var(name),
+    }
+}
+
+/// Check whether a name is a special parameter (`$?`, `$$`, `$1`, ...)
+fn is_special_parameter(name: &str) -> bool {
+    matches!(name, "?" | "$" | "#" | "@" | "*" | "!" | "-")
+        || (!name.is_empty() && name.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Find the index of the `}` closing a `${` whose content starts at `start`
+pub(crate) fn find_closing_brace(chars: &[char], start: usize) -> Option<usize> {
+    let mut depth = 1;
+    let mut i = start;
+
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '\'' => {
+                i += 1;
+                while i < chars.len() && chars[i] != '\'' {
+                    i += 1;
+                }
+            }
+            '"' => {
+                i += 1;
+                while i < chars.len() && chars[i] != '"' {
+                    if chars[i] == '\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+            }
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    None
+}
+
+/// Find the index of the `)` closing a `$(` whose content starts at `start`
+pub(crate) fn find_closing_paren(chars: &[char], start: usize) -> Option<usize> {
+    let mut depth = 1;
+    let mut i = start;
+
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '\'' => {
+                i += 1;
+                while i < chars.len() && chars[i] != '\'' {
+                    i += 1;
+                }
+            }
+            '"' => {
+                i += 1;
+                while i < chars.len() && chars[i] != '"' {
+                    if chars[i] == '\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+            }
+            '`' => i = find_closing_backquote(chars, i + 1)?,
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    None
+}
+
+/// Find the index of the backquote closing one whose content starts at `start`
+pub(crate) fn find_closing_backquote(chars: &[char], start: usize) -> Option<usize> {
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '`' => return Some(i),
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Single pass over a raw word producing expanded segments
+struct WordExpander {
+    chars: Vec<char>,
+    pos: usize,
+    segments: Vec<Segment>,
+    literal: String,
+    /// Start of the value when expanding an assignment, where tildes
+    /// are also recognized after each `:`
+    value_start: Option<usize>,
+    /// Expanding a here-document body, where `"` is an ordinary character
+    heredoc: bool,
+}
+
+impl WordExpander {
+    fn new(raw: &str) -> Self {
+        Self {
+            chars: raw.chars().collect(),
+            pos: 0,
+            segments: Vec::new(),
+            literal: String::new(),
+            value_start: None,
+            heredoc: false,
+        }
+    }
+
+    /// Treat the text from `value_start` on as an assignment value
+    fn assignment(mut self, value_start: usize) -> Self {
+        self.value_start = Some(value_start);
+        self
+    }
+
+    /// Whether a `~` at the current position starts a tilde prefix
+    fn at_tilde_prefix(&self) -> bool {
+        if self.pos == 0 {
+            return true;
+        }
+        match self.value_start {
+            Some(start) if self.pos == start => self.chars[self.pos - 1] == '=',
+            Some(start) if self.pos > start => self.literal.ends_with(':'),
+            _ => false,
+        }
+    }
+
+    /// Expand `~`, `~user`, `~+` or `~-` at the current position.
+    /// Returns false (consuming nothing) if the prefix can't be expanded
+    fn expand_tilde_prefix(&mut self) -> bool {
+        let in_assignment = self.value_start.is_some();
+        let end = (self.pos + 1..self.chars.len())
+            .find(|&i| self.chars[i] == '/' || (in_assignment && self.chars[i] == ':'))
+            .unwrap_or(self.chars.len());
+        let prefix: String = self.chars[self.pos + 1..end].iter().collect();
+
+        // Any quoting or expansion in the prefix disables tilde expansion
+        if prefix.contains(['\\', '\'', '"', '$', '`']) {
+            return false;
+        }
+
+        match expand_tilde(&prefix) {
+            Some(dir) => {
+                self.flush_literal();
+                self.segments.push(Segment::quoted(dir));
+                self.pos = end;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Move pending unquoted literal text into a segment
+    fn flush_literal(&mut self) {
+        if !self.literal.is_empty() {
+            let text = std::mem::take(&mut self.literal);
+            self.segments.push(Segment::literal(text));
+        }
+    }
+
+    fn expand(mut self) -> ShellResult<Vec<Segment>> {
+        while self.pos < self.chars.len() {
+            let c = self.chars[self.pos];
+            match c {
+                '\\' => {
+                    self.flush_literal();
+                    self.pos += 1;
+                    if let Some(&next) = self.chars.get(self.pos) {
+                        self.segments.push(Segment::quoted(next.to_string()));
+                        self.pos += 1;
+                    }
+                }
+                '\'' => {
+                    self.flush_literal();
+                    self.pos += 1;
+                    let mut text = String::new();
+                    while let Some(&ch) = self.chars.get(self.pos) {
+                        self.pos += 1;
+                        if ch == '\'' {
+                            break;
+                        }
+                        text.push(ch);
+                    }
+                    self.segments.push(Segment::quoted(text));
+                }
+                '"' => {
+                    self.flush_literal();
+                    self.pos += 1;
+                    self.expand_double_quoted()?;
+                }
+                '$' => {
+                    self.flush_literal();
+                    self.expand_dollar(false)?;
+                }
+                '`' => {
+                    self.flush_literal();
+                    self.expand_backquoted(false)?;
+                }
+                '<' | '>' if self.chars.get(self.pos + 1) == Some(&'(') => {
+                    self.flush_literal();
+                    self.expand_process_substitution()?;
+                }
+                '~' if self.at_tilde_prefix() && self.expand_tilde_prefix() => {}
+                _ => {
+                    self.literal.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+
+        self.flush_literal();
+        Ok(self.segments)
+    }
+
+    /// Expand `<(...)` or `>(...)` into the path of a pipe to the command
+    fn expand_process_substitution(&mut self) -> ShellResult<()> {
+        let input = self.chars[self.pos] == '<';
+        let end = find_closing_paren(&self.chars, self.pos + 2).ok_or_else(|| {
+            ShellError::SyntaxError("syntax error: unexpected end of file".into())
+        })?;
+        let source: String = self.chars[self.pos + 2..end].iter().collect();
+        self.pos = end + 1;
+
+        let path = process_substitution(&source, input)?;
+        self.segments.push(Segment::quoted(path));
+        Ok(())
+    }
+
+    /// Expand the inside of a double-quoted string, up to the closing quote
+    fn expand_double_quoted(&mut self) -> ShellResult<()> {
+        // An empty pair of quotes still produces an (empty) field
+        self.segments.push(Segment::quoted(String::new()));
+        let mut text = String::new();
+
+        while let Some(&ch) = self.chars.get(self.pos) {
+            match ch {
+                '"' if !self.heredoc => {
+                    self.pos += 1;
+                    break;
+                }
+                // Inside double quotes a backslash only escapes these characters
+                '\\' => {
+                    self.pos += 1;
+                    match self.chars.get(self.pos) {
+                        Some(&next)
+                            if matches!(next, '\\' | '$' | '`')
+                                || (next == '"' && !self.heredoc) =>
+                        {
+                            text.push(next);
+                            self.pos += 1;
+                        }
+                        Some('\n') => self.pos += 1,
+                        _ => text.push('\\'),
+                    }
+                }
+                '$' => {
+                    self.segments
+                        .push(Segment::quoted(std::mem::take(&mut text)));
+                    self.expand_dollar(true)?;
+                }
+                '`' => {
+                    self.segments
+                        .push(Segment::quoted(std::mem::take(&mut text)));
+                    self.expand_backquoted(true)?;
+                }
+                _ => {
+                    text.push(ch);
+                    self.pos += 1;
+                }
+            }
+        }
+
+        self.segments.push(Segment::quoted(text));
+        Ok(())
+    }
+
+    /// Run the backquoted command substitution starting under the cursor
+    fn expand_backquoted(&mut self, quoted: bool) -> ShellResult<()> {
+        let start = self.pos + 1;
+        let end = find_closing_backquote(&self.chars, start).ok_or_else(|| {
+            ShellError::SyntaxError("syntax error: unexpected end of file".to_string())
+        })?;
+        let content: String = self.chars[start..end].iter().collect();
+        self.pos = end + 1;
+
+        let output = command_substitution(&unescape_backquoted(&content))?;
+        self.segments.push(Segment::expanded(output, quoted));
+        Ok(())
+    }
+
+    /// Expand the parameter starting at the `$` under the cursor
+    fn expand_dollar(&mut self, quoted: bool) -> ShellResult<()> {
+        self.pos += 1;
+
+        let value = match self.chars.get(self.pos) {
+            Some('{') => {
+                let start = self.pos + 1;
+                let end = find_closing_brace(&self.chars, start).ok_or_else(|| {
+                    ShellError::SyntaxError("bad substitution: no closing `}'".to_string())
+                })?;
+                let content: String = self.chars[start..end].iter().collect();
+                self.pos = end + 1;
+                expand_braced(&content)?
+            }
+            Some('(') => {
+                let start = self.pos + 1;
+                let end = find_closing_paren(&self.chars, start).ok_or_else(|| {
+                    ShellError::SyntaxError("syntax error: unexpected end of file".to_string())
+                })?;
+                self.pos = end + 1;
+
+                // `$((expr))` is arithmetic when the inner parentheses span the whole content
+                let is_arithmetic = self.chars.get(start) == Some(&'(')
+                    && find_closing_paren(&self.chars, start + 1) == Some(end - 1);
+                if is_arithmetic {
+                    let expr: String = self.chars[start + 1..end - 1].iter().collect();
+                    expand_arithmetic(&expr)?.to_string()
+                } else {
+                    let content: String = self.chars[start..end].iter().collect();
+                    command_substitution(&content)?
+                }
+            }
+            Some('@') if quoted => {
+                self.expand_quoted_at();
+                return Ok(());
+            }
+            Some(&c) if is_special_parameter(&c.to_string()) => {
+                self.pos += 1;
+                lookup_parameter(&c.to_string()).unwrap_or_default()
+            }
+            Some(&c) if c.is_ascii_alphabetic() || c == '_' => {
+                let start = self.pos;
+                while self
+                    .chars
+                    .get(self.pos)
+                    .is_some_and(|c| c.is_ascii_alphanumeric() || *c == '_')
+                {
+                    self.pos += 1;
+                }
+                let name: String = self.chars[start..self.pos].iter().collect();
+                lookup_parameter(&name).unwrap_or_default()
+            }
+            // A `$` not followed by a name is kept literally
+            _ => {
+                if quoted {
+                    self.segments.push(Segment::quoted("$".to_string()));
+                } else {
+                    self.literal.push('$');
+                }
+                return Ok(());
+            }
+        };
+
+        self.segments.push(Segment::expanded(value, quoted));
+        Ok(())
+    }
+
+    /// Expand a double-quoted `$@` into one field per positional parameter
+    fn expand_quoted_at(&mut self) {
+        self.pos += 1;
+        let params = variables::positional_params();
+        if params.is_empty() {
+            self.segments.push(Segment::boundary(FieldMark::Empty));
+        }
+        for (i, param) in params.into_iter().enumerate() {
+            if i > 0 {
+                self.segments.push(Segment::boundary(FieldMark::Break));
+            }
+            self.segments.push(Segment::quoted(param));
+        }
+    }
+}
+
+/// Split expanded segments into fields on `IFS` characters.
+/// Only unquoted expansion results are split; quoted text always stays in one field.
+fn split_fields(segments: Vec<Segment>, ifs: &str) -> Vec<Vec<Segment>> {
+    let mut fields = Vec::new();
+    let mut current: Vec<Segment> = Vec::new();
+    // Whether the current field has content (an empty quoted string counts)
+    let mut started = false;
+    // Whether the current field came from `"$@"` without parameters
+    let mut vanishes = false;
+
+    for segment in segments {
+        match segment.mark {
+            Some(FieldMark::Break) => {
+                fields.push(std::mem::take(&mut current));
+                started = false;
+                vanishes = false;
+                continue;
+            }
+            Some(FieldMark::Empty) => {
+                vanishes = true;
+                continue;
+            }
+            None => {}
+        }
+
+        if !segment.split {
+            started |= segment.quoted || !segment.text.is_empty();
+            current.push(segment);
+            continue;
+        }
+
+        let mut text = String::new();
+        for ch in segment.text.chars() {
+            if !ifs.contains(ch) {
+                text.push(ch);
+                continue;
+            }
+
+            if !text.is_empty() {
+                current.push(Segment::literal(std::mem::take(&mut text)));
+                started = true;
+            }
+            // Runs of IFS whitespace collapse; other IFS characters always delimit
+            if started || !ch.is_whitespace() {
+                fields.push(std::mem::take(&mut current));
+                started = false;
+                vanishes = false;
+            }
+        }
+
+        if !text.is_empty() {
+            current.push(Segment::literal(text));
+            started = true;
+        }
+    }
+
+    if started && !(vanishes && current.iter().all(|s| s.text.is_empty())) {
+        fields.push(current);
+    }
+
+    fields
+}
@@ -0,0 +1,432 @@
+use super::pattern::Pattern;
+use super::{
+    expand_arithmetic, expand_pattern, expand_word, is_special_parameter, lookup_parameter,
+};
+use crate::errors::{ShellError, ShellResult};
+use crate::parser::ast::Word;
+use crate::variables::{self, is_valid_name};
+
+/// Expand the content of a `${...}` expression
+pub(super) fn expand_braced(content: &str) -> ShellResult<String> {
+    // ${#NAME}: length of the value
+    if let Some(name) = content.strip_prefix('#') {
+        if is_valid_name(name) || is_special_parameter(name) {
+            let value = lookup_parameter(name).unwrap_or_default();
+            return Ok(value.chars().count().to_string());
+        }
+    }
+
+    let (name, operation) = split_parameter_name(content);
+    if name.is_empty() {
+        return Err(bad_substitution(content));
+    }
+
+    let value = lookup_parameter(name);
+    if operation.is_empty() {
+        return Ok(value.unwrap_or_default());
+    }
+
+    apply_operation(content, name, value, operation)
+}
+
+fn bad_substitution(content: &str) -> ShellError {
+    ShellError::ExpansionError(format!("${{{}}}: bad substitution", content))
+}
+
+/// Split `NAME<operation>` into the parameter name and the rest
+fn split_parameter_name(content: &str) -> (&str, &str) {
+    let first = match content.chars().next() {
+        Some(c) => c,
+        None => return ("", ""),
+    };
+
+    let len = if first.is_ascii_digit() {
+        content.chars().take_while(|c| c.is_ascii_digit()).count()
+    } else if is_special_parameter(&first.to_string()) {
+        1
+    } else {
+        content
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+            .count()
+    };
+
+    content.split_at(len)
+}
+
+/// Apply a parameter expansion operator to a (possibly unset) value
+fn apply_operation(
+    content: &str,
+    name: &str,
+    value: Option<String>,
+    operation: &str,
+) -> ShellResult<String> {
+    // With a colon, an empty value is treated like an unset one
+    let (check_null, op) = match operation.strip_prefix(':') {
+        Some(rest) if rest.starts_with(['-', '=', '?', '+']) => (true, rest),
+        _ => (false, operation),
+    };
+    let is_set = match &value {
+        Some(v) => !(check_null && v.is_empty()),
+        None => false,
+    };
+
+    let mut chars = op.chars();
+    let first = chars.next().unwrap_or_default();
+    let rest = chars.as_str();
+
+    match first {
+        '-' => match value {
+            Some(v) if is_set => Ok(v),
+            _ => expand_operand(rest),
+        },
+        '=' => match value {
+            Some(v) if is_set => Ok(v),
+            _ => {
+                if !is_valid_name(name) {
+                    return Err(ShellError::ExpansionError(format!(
+                        "${}: cannot assign in this way",
+                        name
+                    )));
+                }
+                let default = expand_operand(rest)?;
+                variables::set_var(name, &default);
+                Ok(default)
+            }
+        },
+        '?' => match value {
+            Some(v) if is_set => Ok(v),
+            _ => {
+                let message = if !rest.is_empty() {
+                    expand_operand(rest)?
+                } else if check_null {
+                    "parameter null or not set".to_string()
+                } else {
+                    // Without the colon only an unset parameter is an error
+                    "parameter not set".to_string()
+                };
+                Err(ShellError::ExpansionError(format!("{}: {}", name, message)))
+            }
+        },
+        '+' => {
+            if is_set {
+                expand_operand(rest)
+            } else {
+                Ok(String::new())
+            }
+        }
+        '#' | '%' => {
+            let value = value.unwrap_or_default();
+            let longest = rest.starts_with(first);
+            let pattern_text = if longest { &rest[1..] } else { rest };
+            let pattern = Pattern::new(&expand_pattern(pattern_text)?);
+            let chars: Vec<char> = value.chars().collect();
+
+            Ok(if first == '#' {
+                match pattern.match_prefix(&value, longest) {
+                    Some(len) => chars[len..].iter().collect(),
+                    None => value,
+                }
+            } else {
+                match pattern.match_suffix(&value, longest) {
+                    Some(start) => chars[..start].iter().collect(),
+                    None => value,
+                }
+            })
+        }
+        '/' => replace_pattern(&value.unwrap_or_default(), rest),
+        '^' | ',' => {
+            let value = value.unwrap_or_default();
+            let all = rest.starts_with(first);
+            let pattern_text = if all { &rest[1..] } else { rest };
+            let pattern = if pattern_text.is_empty() {
+                None
+            } else {
+                Some(Pattern::new(&expand_pattern(pattern_text)?))
+            };
+            Ok(modify_case(&value, first == '^', all, pattern.as_ref()))
+        }
+        ':' => substring(&value.unwrap_or_default(), rest),
+        _ => Err(bad_substitution(content)),
+    }
+}
+
+/// Expand the word operand of an operator (tilde, parameters, quotes)
+fn expand_operand(operand: &str) -> ShellResult<String> {
+    expand_word(&Word(operand.to_string()))
+}
+
+/// `${VAR/pat/rep}`, `${VAR//pat/rep}`, `${VAR/#pat/rep}` and `${VAR/%pat/rep}`
+fn replace_pattern(value: &str, spec: &str) -> ShellResult<String> {
+    let (mode, spec) = match spec.chars().next() {
+        Some(c @ ('/' | '#' | '%')) => (Some(c), &spec[1..]),
+        _ => (None, spec),
+    };
+
+    let (pattern_text, replacement) = split_replacement(spec);
+    if pattern_text.is_empty() {
+        return Ok(value.to_string());
+    }
+
+    let pattern = Pattern::new(&expand_pattern(pattern_text)?);
+    let replacement = match replacement {
+        Some(r) => expand_operand(r)?,
+        None => String::new(),
+    };
+    let chars: Vec<char> = value.chars().collect();
+
+    match mode {
+        Some('#') => Ok(match pattern.match_prefix(value, true) {
+            Some(len) => replacement + &chars[len..].iter().collect::<String>(),
+            None => value.to_string(),
+        }),
+        Some('%') => Ok(match pattern.match_suffix(value, true) {
+            Some(start) => chars[..start].iter().collect::<String>() + &replacement,
+            None => value.to_string(),
+        }),
+        _ => {
+            let global = mode == Some('/');
+            let mut result = String::new();
+            let mut pos = 0;
+
+            while let Some((start, end)) = pattern.find(&chars, pos) {
+                result.extend(&chars[pos..start]);
+                result.push_str(&replacement);
+                if end == start {
+                    // Empty match: copy one char to make progress
+                    if let Some(&c) = chars.get(start) {
+                        result.push(c);
+                    }
+                    pos = start + 1;
+                } else {
+                    pos = end;
+                }
+                if !global || pos > chars.len() {
+                    break;
+                }
+            }
+
+            if pos <= chars.len() {
+                result.extend(&chars[pos..]);
+            }
+            Ok(result)
+        }
+    }
+}
+
+/// Split `pat/rep` at the first unescaped, unquoted `/`
+fn split_replacement(spec: &str) -> (&str, Option<&str>) {
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+
+    for (i, c) in spec.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' => escaped = true,
+            '\'' | '"' if quote.is_none() => quote = Some(c),
+            c if quote == Some(c) => quote = None,
+            '/' if quote.is_none() => return (&spec[..i], Some(&spec[i + 1..])),
+            _ => {}
+        }
+    }
+
+    (spec, None)
+}
+
+/// `${VAR^}`, `${VAR^^}`, `${VAR,}` and `${VAR,,}` with an optional pattern
+fn modify_case(value: &str, upper: bool, all: bool, pattern: Option<&Pattern>) -> String {
+    value
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            let selected = (all || i == 0) && pattern.map_or(true, |p| p.matches(&c.to_string()));
+            match (selected, upper) {
+                (false, _) => c.to_string(),
+                (true, true) => c.to_uppercase().to_string(),
+                (true, false) => c.to_lowercase().to_string(),
+            }
+        })
+        .collect()
+}
+
+/// `${VAR:offset}` and `${VAR:offset:length}`; negative values count from the end
+fn substring(value: &str, spec: &str) -> ShellResult<String> {
+    let chars: Vec<char> = value.chars().collect();
+    let len = chars.len() as i64;
+
+    let (offset_text, length_text) = match spec.split_once(':') {
+        Some((offset, length)) => (offset, Some(length)),
+        None => (spec, None),
+    };
+
+    // Offsets and lengths can be anything an arithmetic expression evaluates
+    // to, so saturate rather than overflow before clamping to the value
+    let mut start = evaluate_offset(offset_text)?;
+    if start < 0 {
+        start = start.saturating_add(len);
+        if start < 0 {
+            return Ok(String::new());
+        }
+    }
+    let start = start.min(len);
+
+    let end = match length_text {
+        None => len,
+        Some(text) => {
+            let length = evaluate_offset(text)?;
+            if length < 0 {
+                let end = len.saturating_add(length);
+                if end < start {
+                    return Err(ShellError::ExpansionError(format!(
+                        "{}: substring expression < 0",
+                        length
+                    )));
+                }
+                end
+            } else {
+                start.saturating_add(length).min(len)
+            }
+        }
+    };
+
+    Ok(chars[start as usize..end as usize].iter().collect())
+}
+
+/// Evaluate an offset or length operand of a substring expansion
+fn evaluate_offset(text: &str) -> ShellResult<i64> {
+    expand_arithmetic(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Expand `${name<operation>}` with `name` set to `value`, or unset
+    fn expand(name: &str, value: Option<&str>, operation: &str) -> ShellResult<String> {
+        match value {
+            Some(value) => variables::set_var(name, value),
+            None => {
+                variables::unset_var(name);
+            }
+        }
+        expand_braced(&format!("{}{}", name, operation))
+    }
+
+    fn error_message(result: ShellResult<String>) -> String {
+        match result {
+            Err(ShellError::ExpansionError(message)) => message,
+            other => panic!("expected an expansion error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn defaults_and_alternatives() {
+        let name = "param_test_a";
+        assert_eq!(expand(name, None, "-x").unwrap(), "x");
+        assert_eq!(expand(name, Some(""), "-x").unwrap(), "");
+        assert_eq!(expand(name, Some(""), ":-x").unwrap(), "x");
+        assert_eq!(expand(name, Some("v"), ":-x").unwrap(), "v");
+        assert_eq!(expand(name, None, "+x").unwrap(), "");
+        assert_eq!(expand(name, Some(""), "+x").unwrap(), "x");
+        assert_eq!(expand(name, Some(""), ":+x").unwrap(), "");
+        assert_eq!(expand(name, Some("v"), ":+x").unwrap(), "x");
+    }
+
+    #[test]
+    fn assign_default() {
+        let name = "param_test_b";
+        assert_eq!(expand(name, None, ":=y").unwrap(), "y");
+        assert_eq!(variables::get_var(name).as_deref(), Some("y"));
+        assert_eq!(expand(name, Some(""), "=z").unwrap(), "");
+        assert_eq!(expand(name, Some(""), ":=z").unwrap(), "z");
+        assert_eq!(variables::get_var(name).as_deref(), Some("z"));
+        assert!(expand_braced("1:=x").is_err());
+    }
+
+    #[test]
+    fn unset_errors() {
+        let name = "param_test_c";
+        assert_eq!(
+            error_message(expand(name, None, "?")),
+            "param_test_c: parameter not set"
+        );
+        assert_eq!(
+            error_message(expand(name, Some(""), ":?")),
+            "param_test_c: parameter null or not set"
+        );
+        assert_eq!(
+            error_message(expand(name, None, ":?gone")),
+            "param_test_c: gone"
+        );
+        assert_eq!(expand(name, Some(""), "?").unwrap(), "");
+        assert_eq!(expand(name, Some("v"), ":?").unwrap(), "v");
+    }
+
+    #[test]
+    fn prefix_and_suffix_removal() {
+        let (name, path) = ("param_test_d", Some("/usr/local/lib.tar.gz"));
+        assert_eq!(expand(name, path, "#*/").unwrap(), "usr/local/lib.tar.gz");
+        assert_eq!(expand(name, path, "##*/").unwrap(), "lib.tar.gz");
+        assert_eq!(expand(name, path, "%.*").unwrap(), "/usr/local/lib.tar");
+        assert_eq!(expand(name, path, "%%.*").unwrap(), "/usr/local/lib");
+        assert_eq!(expand(name, path, "#x").unwrap(), "/usr/local/lib.tar.gz");
+    }
+
+    #[test]
+    fn pattern_replacement() {
+        let (name, value) = ("param_test_e", Some("a-b-c"));
+        assert_eq!(expand(name, value, "/-/+").unwrap(), "a+b-c");
+        assert_eq!(expand(name, value, "//-/+").unwrap(), "a+b+c");
+        assert_eq!(expand(name, value, "//-").unwrap(), "abc");
+        assert_eq!(expand(name, value, "/#a/x").unwrap(), "x-b-c");
+        assert_eq!(expand(name, value, "/#b/x").unwrap(), "a-b-c");
+        assert_eq!(expand(name, value, "/%c/x").unwrap(), "a-b-x");
+        assert_eq!(expand(name, value, "/%b/x").unwrap(), "a-b-c");
+        assert_eq!(expand(name, value, "//[ab]/?").unwrap(), "?-?-c");
+        assert_eq!(expand(name, value, "/*-/").unwrap(), "c");
+    }
+
+    #[test]
+    fn case_modification() {
+        let (name, value) = ("param_test_f", Some("hello World"));
+        assert_eq!(expand(name, value, "^").unwrap(), "Hello World");
+        assert_eq!(expand(name, value, "^^").unwrap(), "HELLO WORLD");
+        assert_eq!(expand(name, value, ",").unwrap(), "hello World");
+        assert_eq!(expand(name, value, ",,").unwrap(), "hello world");
+        assert_eq!(expand(name, value, "^^[lo]").unwrap(), "heLLO WOrLd");
+    }
+
+    #[test]
+    fn substrings() {
+        let (name, value) = ("param_test_g", Some("hello"));
+        assert_eq!(expand(name, value, ":1").unwrap(), "ello");
+        assert_eq!(expand(name, value, ":1:3").unwrap(), "ell");
+        assert_eq!(expand(name, value, ": -3").unwrap(), "llo");
+        assert_eq!(expand(name, value, ": -3:2").unwrap(), "ll");
+        assert_eq!(expand(name, value, ":1:-1").unwrap(), "ell");
+        assert_eq!(expand(name, value, ": -9").unwrap(), "");
+        assert_eq!(expand(name, value, ":9").unwrap(), "");
+        assert_eq!(
+            error_message(expand(name, value, ":3:-3")),
+            "-3: substring expression < 0"
+        );
+    }
+
+    #[test]
+    fn substrings_saturate() {
+        let (name, value) = ("param_test_h", Some("hello"));
+        let max = i64::MAX;
+        assert_eq!(expand(name, value, &format!(":1:{}", max)).unwrap(), "ello");
+        assert_eq!(expand(name, value, &format!(":{}", max)).unwrap(), "");
+        assert_eq!(
+            expand(name, value, &format!(":{}:{}", max, max)).unwrap(),
+            ""
+        );
+        assert_eq!(expand(name, value, &format!(": -{}", max)).unwrap(), "");
+        assert_eq!(expand(name, value, &format!(": -{}:2", max)).unwrap(), "");
+        assert!(expand(name, value, &format!(":1:-{}", max)).is_err());
+    }
+}
@@ -0,0 +1,361 @@
+/// One element of a compiled shell pattern
+#[derive(Debug, Clone)]
+enum PatternToken {
+    Literal(char),
+    /// `?`
+    AnyChar,
+    /// `*`
+    AnyString,
+    /// `[...]` bracket expression
+    Class {
+        negated: bool,
+        items: Vec<ClassItem>,
+    },
+}
+
+#[derive(Debug, Clone)]
+enum ClassItem {
+    Char(char),
+    Range(char, char),
+    Named(String),
+}
+
+impl ClassItem {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            ClassItem::Char(ch) => *ch == c,
+            ClassItem::Range(lo, hi) => *lo <= c && c <= *hi,
+            ClassItem::Named(name) => match name.as_str() {
+                "alpha" => c.is_alphabetic(),
+                "digit" => c.is_ascii_digit(),
+                "alnum" => c.is_alphanumeric(),
+                "upper" => c.is_uppercase(),
+                "lower" => c.is_lowercase(),
+                "space" => c.is_whitespace(),
+                "blank" => c == ' ' || c == '\t',
+                "punct" => c.is_ascii_punctuation(),
+                "xdigit" => c.is_ascii_hexdigit(),
+                "cntrl" => c.is_control(),
+                "print" => !c.is_control(),
+                "graph" => !c.is_control() && !c.is_whitespace(),
+                _ => false,
+            },
+        }
+    }
+}
+
+impl PatternToken {
+    /// Whether this token consumes exactly the character `c`
+    fn matches_char(&self, c: char) -> bool {
+        match self {
+            PatternToken::Literal(ch) => *ch == c,
+            PatternToken::AnyChar => true,
+            PatternToken::AnyString => true,
+            PatternToken::Class { negated, items } => {
+                items.iter().any(|item| item.matches(c)) != *negated
+            }
+        }
+    }
+}
+
+/// A shell pattern (`*`, `?`, `[...]`, backslash escapes) compiled for matching
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    tokens: Vec<PatternToken>,
+}
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Self {
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut tokens = Vec::new();
+        let mut i = 0;
+
+        while i < chars.len() {
+            match chars[i] {
+                '\\' if i + 1 < chars.len() => {
+                    tokens.push(PatternToken::Literal(chars[i + 1]));
+                    i += 2;
+                }
+                '?' => {
+                    tokens.push(PatternToken::AnyChar);
+                    i += 1;
+                }
+                '*' => {
+                    // Consecutive stars are equivalent to one
+                    if !matches!(tokens.last(), Some(PatternToken::AnyString)) {
+                        tokens.push(PatternToken::AnyString);
+                    }
+                    i += 1;
+                }
+                '[' => match parse_class(&chars, i + 1) {
+                    Some((token, next)) => {
+                        tokens.push(token);
+                        i = next;
+                    }
+                    // An unterminated bracket matches itself
+                    None => {
+                        tokens.push(PatternToken::Literal('['));
+                        i += 1;
+                    }
+                },
+                c => {
+                    tokens.push(PatternToken::Literal(c));
+                    i += 1;
+                }
+            }
+        }
+
+        Self { tokens }
+    }
+
+    /// Check whether the whole of `text` matches the pattern
+    pub fn matches(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        self.matches_chars(&text)
+    }
+
+    fn matches_chars(&self, text: &[char]) -> bool {
+        let tokens = &self.tokens;
+        let (mut t, mut p) = (0, 0);
+        // Position of the last `*` and the text index it was tried at
+        let mut backtrack: Option<(usize, usize)> = None;
+
+        while t < text.len() {
+            match tokens.get(p) {
+                Some(PatternToken::AnyString) => {
+                    backtrack = Some((p, t));
+                    p += 1;
+                }
+                Some(token) if token.matches_char(text[t]) => {
+                    p += 1;
+                    t += 1;
+                }
+                _ => match backtrack {
+                    // Let the last star swallow one more character and retry
+                    Some((star_p, star_t)) => {
+                        backtrack = Some((star_p, star_t + 1));
+                        p = star_p + 1;
+                        t = star_t + 1;
+                    }
+                    None => return false,
+                },
+            }
+        }
+
+        tokens[p..]
+            .iter()
+            .all(|token| matches!(token, PatternToken::AnyString))
+    }
+
+    /// Length in chars of the shortest or longest prefix of `text` matching the pattern
+    pub fn match_prefix(&self, text: &str, longest: bool) -> Option<usize> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut lengths: Vec<usize> = (0..=chars.len()).collect();
+        if longest {
+            lengths.reverse();
+        }
+        lengths
+            .into_iter()
+            .find(|&len| self.matches_chars(&chars[..len]))
+    }
+
+    /// Start index (in chars) of the shortest or longest suffix of `text` matching the pattern
+    pub fn match_suffix(&self, text: &str, longest: bool) -> Option<usize> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut starts: Vec<usize> = (0..=chars.len()).collect();
+        if !longest {
+            starts.reverse();
+        }
+        starts
+            .into_iter()
+            .find(|&start| self.matches_chars(&chars[start..]))
+    }
+
+    /// Find the leftmost, longest match at or after char index `from`.
+    /// Returns the (start, end) char indices of the match
+    pub fn find(&self, chars: &[char], from: usize) -> Option<(usize, usize)> {
+        let fixed_length = self.fixed_length();
+        (from..=chars.len())
+            .filter(|&start| self.can_start(chars.get(start).copied()))
+            .find_map(|start| match fixed_length {
+                Some(length) => {
+                    let end = start + length;
+                    (end <= chars.len() && self.matches_chars(&chars[start..end]))
+                        .then_some((start, end))
+                }
+                None => (start..=chars.len())
+                    .rev()
+                    .find(|&end| self.matches_chars(&chars[start..end]))
+                    .map(|end| (start, end)),
+            })
+    }
+
+    /// Length in chars of every match, if the pattern has no `*`
+    fn fixed_length(&self) -> Option<usize> {
+        let has_star = self
+            .tokens
+            .iter()
+            .any(|token| matches!(token, PatternToken::AnyString));
+        (!has_star).then_some(self.tokens.len())
+    }
+
+    /// Whether a match could start at `c` (`None` is the end of the text)
+    fn can_start(&self, c: Option<char>) -> bool {
+        match (self.tokens.first(), c) {
+            (None | Some(PatternToken::AnyString), _) => true,
+            (Some(token), Some(c)) => token.matches_char(c),
+            (Some(_), None) => false,
+        }
+    }
+}
+
+/// Parse a bracket expression whose content starts at `start`.
+/// Returns the token and the index just past the closing `]`
+fn parse_class(chars: &[char], start: usize) -> Option<(PatternToken, usize)> {
+    let mut i = start;
+    let negated = matches!(chars.get(i), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut items = Vec::new();
+    let mut first = true;
+
+    loop {
+        let c = *chars.get(i)?;
+        match c {
+            // A `]` right after the opening bracket is a literal
+            ']' if !first => return Some((PatternToken::Class { negated, items }, i + 1)),
+            '[' if chars.get(i + 1) == Some(&':') => {
+                let rest: String = chars[i + 2..].iter().collect();
+                let end = rest.find(":]")?;
+                items.push(ClassItem::Named(rest[..end].to_string()));
+                i += 2 + rest[..end].chars().count() + 2;
+            }
+            _ => {
+                let (c, next) = if c == '\\' && i + 1 < chars.len() {
+                    (chars[i + 1], i + 2)
+                } else {
+                    (c, i + 1)
+                };
+                i = next;
+
+                // Range like `a-z` (a trailing `-` is literal)
+                if chars.get(i) == Some(&'-') && chars.get(i + 1).is_some_and(|&c| c != ']') {
+                    items.push(ClassItem::Range(c, chars[i + 1]));
+                    i += 2;
+                } else {
+                    items.push(ClassItem::Char(c));
+                }
+            }
+        }
+        first = false;
+    }
+}
+
+/// Check whether `text` contains unescaped pattern metacharacters
+pub fn has_pattern_chars(text: &str) -> bool {
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '*' | '?' | '[' => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Remove the backslashes protecting escaped characters in a pattern
+pub fn unescape_pattern(text: &str) -> String {
+    let mut result = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => result.extend(chars.next()),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// Escape pattern metacharacters so the text only matches itself
+pub fn escape_pattern(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, text: &str) -> bool {
+        Pattern::new(pattern).matches(text)
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(!matches("*.rs", "main.rs.bak"));
+        assert!(matches("a*b*c", "aXXbYYc"));
+        assert!(matches("?x?", "axb"));
+        assert!(!matches("?", ""));
+        assert!(matches("*", ""));
+    }
+
+    #[test]
+    fn bracket_expressions() {
+        assert!(matches("[!a-c]x", "dx"));
+        assert!(!matches("[!a-c]x", "bx"));
+        assert!(matches("[^a-c]", "z"));
+        assert!(matches("[[:alpha:]][[:digit:]]", "a1"));
+        assert!(!matches("[[:alpha:]]", "1"));
+        assert!(matches("[]a]", "]"));
+        assert!(matches("[a-]", "-"));
+        // An unterminated bracket matches itself
+        assert!(matches("[ab", "[ab"));
+    }
+
+    #[test]
+    fn escaped_metacharacters() {
+        assert!(matches(r"\*", "*"));
+        assert!(!matches(r"\*", "x"));
+        assert!(matches(r"a\?b", "a?b"));
+        assert!(!matches(r"a\?b", "axb"));
+        assert!(matches(r"\[x]", "[x]"));
+        assert!(matches(&escape_pattern("a*b[1]"), "a*b[1]"));
+        assert!(!has_pattern_chars(r"a\*b"));
+        assert!(has_pattern_chars("a[b"));
+        assert_eq!(unescape_pattern(r"a\*b\\"), r"a*b\");
+    }
+
+    #[test]
+    fn prefix_and_suffix_matches() {
+        let pattern = Pattern::new("*/");
+        assert_eq!(pattern.match_prefix("a/b/c", false), Some(2));
+        assert_eq!(pattern.match_prefix("a/b/c", true), Some(4));
+        let pattern = Pattern::new(".*");
+        assert_eq!(pattern.match_suffix("f.tar.gz", false), Some(5));
+        assert_eq!(pattern.match_suffix("f.tar.gz", true), Some(1));
+    }
+
+    #[test]
+    fn leftmost_longest_find() {
+        let chars: Vec<char> = "xabcabc".chars().collect();
+        assert_eq!(Pattern::new("a").find(&chars, 0), Some((1, 2)));
+        assert_eq!(Pattern::new("a").find(&chars, 2), Some((4, 5)));
+        assert_eq!(Pattern::new("b?").find(&chars, 0), Some((2, 4)));
+        assert_eq!(Pattern::new("a*c").find(&chars, 0), Some((1, 7)));
+        assert_eq!(Pattern::new("*").find(&chars, 7), Some((7, 7)));
+        assert_eq!(Pattern::new("z").find(&chars, 0), None);
+        assert_eq!(Pattern::new("c?").find(&chars, 5), None);
+    }
+}
@@ -0,0 +1,170 @@
+use crate::errors::{report_error, ShellResult};
+use crate::executor::execute_list;
+use crate::parser::parse;
+use crate::process::{create_pipe, fork_shell, wait_for_child};
+use crate::status;
+use once_cell::sync::Lazy;
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+use std::sync::Mutex;
+
+/// Process substitutions still running: the child and the shell's end of its pipe
+static PROCESS_SUBSTITUTIONS: Lazy<Mutex<Vec<(libc::pid_t, File)>>> =
+    Lazy::new(|| Mutex::new(Vec::new()));
+
+/// Run a command substitution and return its output with trailing newlines removed.
+/// The command runs in a forked child with stdout connected to a pipe, so
+/// builtins, externals and pipelines can all be captured
+pub(super) fn command_substitution(source: &str) -> ShellResult<String> {
+    let (mut reader, writer) = create_pipe()?;
+
+    let pid = fork_shell(|| {
+        // Child: stdout goes into the pipe, then run the commands
+        unsafe {
+            libc::dup2(writer.as_raw_fd(), libc::STDOUT_FILENO);
+        }
+
+        match parse(source).and_then(|list| execute_list(&list)) {
+            Ok(code) => code,
+            Err(e) => {
+                report_error(&e);
+                e.exit_status()
+            }
+        }
+    })?;
+
+    // Parent: collect everything the child writes, then reap it
+    drop(writer);
+    let mut output = Vec::new();
+    reader.read_to_end(&mut output)?;
+    drop(reader);
+
+    let code = wait_for_child(pid);
+    status::set_last_status(code);
+    status::set_substitution_status(code);
+
+    let mut output = String::from_utf8_lossy(&output).into_owned();
+    while output.ends_with('\n') {
+        output.pop();
+    }
+    Ok(output)
+}
+
+/// Start a process substitution and return the `/dev/fd/N` path of its pipe.
+/// For `<(cmd)` (`input`) the path reads the command's output; for `>(cmd)`
+/// writing to the path feeds the command's input
+pub(super) fn process_substitution(source: &str, input: bool) -> ShellResult<String> {
+    let (reader, writer) = create_pipe()?;
+    let (ours, theirs) = if input {
+        (reader, writer)
+    } else {
+        (writer, reader)
+    };
+    let target = if input {
+        libc::STDOUT_FILENO
+    } else {
+        libc::STDIN_FILENO
+    };
+
+    let child_end = theirs.as_raw_fd();
+    let our_fd = ours.as_raw_fd();
+    let pid = fork_shell(|| {
+        // The child must not hold the other end, or it would never see EOF,
+        // nor the ends of earlier substitutions, or they would never see it
+        unsafe {
+            libc::close(our_fd);
+            libc::dup2(child_end, target);
+        }
+        if let Ok(mut running) = PROCESS_SUBSTITUTIONS.lock() {
+            running.clear();
+        }
+
+        match parse(source).and_then(|list| execute_list(&list)) {
+            Ok(code) => code,
+            Err(e) => {
+                report_error(&e);
+                e.exit_status()
+            }
+        }
+    })?;
+    drop(theirs);
+
+    // Our end stays close-on-exec; only the command using the path inherits
+    // it (see `inherit_process_substitutions`)
+    if let Ok(mut running) = PROCESS_SUBSTITUTIONS.lock() {
+        running.push((pid, ours));
+    }
+
+    Ok(format!("/dev/fd/{}", our_fd))
+}
+
+/// Number of process substitutions started so far, to pass to
+/// `reap_process_substitutions` once the command using them is done
+pub fn process_substitution_mark() -> usize {
+    PROCESS_SUBSTITUTIONS
+        .lock()
+        .map(|running| running.len())
+        .unwrap_or(0)
+}
+
+/// Let an external command inherit the pipes of the running process
+/// substitutions, so it can open their `/dev/fd/N` paths
+pub fn inherit_process_substitutions(cmd: &mut Command) {
+    let fds: Vec<RawFd> = PROCESS_SUBSTITUTIONS
+        .lock()
+        .map(|running| running.iter().map(|(_, pipe)| pipe.as_raw_fd()).collect())
+        .unwrap_or_default();
+    if fds.is_empty() {
+        return;
+    }
+
+    unsafe {
+        cmd.pre_exec(move || {
+            for &fd in &fds {
+                if libc::fcntl(fd, libc::F_SETFD, 0) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+}
+
+/// Close the pipes of the process substitutions started since `mark` and
+/// wait for their commands to finish
+pub fn reap_process_substitutions(mark: usize) {
+    let finished: Vec<(libc::pid_t, File)> = match PROCESS_SUBSTITUTIONS.lock() {
+        Ok(mut running) if running.len() > mark => running.drain(mark..).collect(),
+        _ => return,
+    };
+
+    // Close every pipe first: a command may hold on until another one exits
+    let pids: Vec<libc::pid_t> = finished.into_iter().map(|(pid, _)| pid).collect();
+    for pid in pids {
+        wait_for_child(pid);
+    }
+}
+
+/// Undo the backslash escaping inside a backquoted substitution
+pub(super) fn unescape_backquoted(source: &str) -> String {
+    let mut result = String::new();
+    let mut chars = source.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            if let Some(&next) = chars.peek() {
+                if matches!(next, '$' | '`' | '\\') {
+                    result.push(next);
+                    chars.next();
+                    continue;
+                }
+            }
+        }
+        result.push(c);
+    }
+
+    result
+}
@@ -0,0 +1,40 @@
+use crate::variables;
+use std::env;
+use std::ffi::{CStr, CString};
+
+/// Expand a tilde prefix (the text between `~` and the first `/`).
+/// Returns None when the prefix doesn't name a directory, in which case
+/// the tilde is kept as written
+pub(super) fn expand_tilde(prefix: &str) -> Option<String> {
+    match prefix {
+        "" => variables::get_var("HOME").or_else(current_user_home),
+        "+" => variables::get_var("PWD").or_else(|| {
+            env::current_dir()
+                .ok()
+                .map(|dir| dir.to_string_lossy().into_owned())
+        }),
+        "-" => variables::get_var("OLDPWD"),
+        user => user_home(user),
+    }
+}
+
+/// Home directory of a user from the passwd database
+fn user_home(user: &str) -> Option<String> {
+    let name = CString::new(user).ok()?;
+    let entry = unsafe { libc::getpwnam(name.as_ptr()) };
+    passwd_dir(entry)
+}
+
+/// Home directory of the current user from the passwd database
+fn current_user_home() -> Option<String> {
+    let entry = unsafe { libc::getpwuid(libc::getuid()) };
+    passwd_dir(entry)
+}
+
+fn passwd_dir(entry: *mut libc::passwd) -> Option<String> {
+    if entry.is_null() {
+        return None;
+    }
+    let dir = unsafe { CStr::from_ptr((*entry).pw_dir) };
+    Some(dir.to_string_lossy().into_owned())
+}
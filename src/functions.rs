@@ -0,0 +1,72 @@
+use crate::control;
+use crate::errors::ShellResult;
+use crate::executor::execute_compound;
+use crate::parser::ast::FunctionDef;
+use crate::variables;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Shell functions by name
+static FUNCTIONS: Lazy<Mutex<HashMap<String, FunctionDef>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Number of function calls currently running
+static CALL_DEPTH: AtomicUsize = AtomicUsize::new(0);
+
+/// Define (or redefine) a function
+pub fn define_function(def: FunctionDef) {
+    if let Ok(mut functions) = FUNCTIONS.lock() {
+        functions.insert(def.name.clone(), def);
+    }
+}
+
+/// Look up a function by name
+pub fn get_function(name: &str) -> Option<FunctionDef> {
+    FUNCTIONS
+        .lock()
+        .ok()
+        .and_then(|functions| functions.get(name).cloned())
+}
+
+/// Check whether a function with this name is defined
+pub fn is_function(name: &str) -> bool {
+    FUNCTIONS
+        .lock()
+        .map(|functions| functions.contains_key(name))
+        .unwrap_or(false)
+}
+
+/// Remove a function, returning whether it existed
+pub fn remove_function(name: &str) -> bool {
+    FUNCTIONS
+        .lock()
+        .map(|mut functions| functions.remove(name).is_some())
+        .unwrap_or(false)
+}
+
+/// Whether a function is currently running
+pub fn in_function() -> bool {
+    CALL_DEPTH.load(Ordering::SeqCst) > 0
+}
+
+/// Run a function with `args[1..]` as its positional parameters and a new
+/// scope for `local` variables. Returns the status given to `return`, or
+/// that of the last command run
+pub fn call_function(def: &FunctionDef, args: &[String]) -> ShellResult<i32> {
+    CALL_DEPTH.fetch_add(1, Ordering::SeqCst);
+    let saved_params = variables::set_positional_params(args[1..].to_vec());
+    variables::push_local_scope();
+
+    let result = execute_compound(&def.body, &def.redirects);
+
+    variables::pop_local_scope();
+    variables::set_positional_params(saved_params);
+    CALL_DEPTH.fetch_sub(1, Ordering::SeqCst);
+
+    match control::take_return() {
+        Some(status) => Ok(status),
+        None => result,
+    }
+}
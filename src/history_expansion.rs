@@ -0,0 +1,620 @@
+use crate::errors::{ShellError, ShellResult};
+use once_cell::sync::Lazy;
+use std::sync::Mutex;
+
+/// Characters that end the string of a `!string` event
+const EVENT_DELIMITERS: &str = " \t\n:;&|()<>\"'`";
+
+/// Characters that form operator words when a history entry is split
+const OPERATOR_CHARS: &str = ";&|()<>";
+
+/// Pattern and replacement of the last `:s` substitution, reused by `:&`,
+/// `^old^new` and `:s` with an empty pattern
+static LAST_SUBSTITUTION: Lazy<Mutex<Option<(String, String)>>> = Lazy::new(|| Mutex::new(None));
+
+/// A line after history expansion
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expansion {
+    pub line: String,
+    /// Whether the line contained any history reference
+    pub expanded: bool,
+    /// Whether a `:p` modifier asked for the line to be printed, not run
+    pub print_only: bool,
+}
+
+/// Expand the history references in `line` (`!!`, `!n`, `!-n`, `!string`,
+/// `!?string?`, `!#`, `!$`... and a leading `^old^new^`) using `history`,
+/// oldest entry first. Nothing is expanded inside single quotes
+pub fn expand(line: &str, history: &[String]) -> ShellResult<Expansion> {
+    let mut expander = Expander {
+        chars: line.chars().collect(),
+        pos: 0,
+        history,
+        out: String::new(),
+        expanded: false,
+        print_only: false,
+    };
+    expander.run()?;
+
+    Ok(Expansion {
+        line: expander.out,
+        expanded: expander.expanded,
+        print_only: expander.print_only,
+    })
+}
+
+struct Expander<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    history: &'a [String],
+    out: String,
+    expanded: bool,
+    print_only: bool,
+}
+
+impl Expander<'_> {
+    fn run(&mut self) -> ShellResult<()> {
+        if self.chars.first() == Some(&'^') {
+            self.quick_substitution()?;
+        }
+
+        let mut in_single = false;
+        let mut in_double = false;
+        while let Some(&c) = self.chars.get(self.pos) {
+            match c {
+                '\'' if !in_double => in_single = !in_single,
+                '"' if !in_single => in_double = !in_double,
+                // An escaped character, `!` included, is kept as it is
+                '\\' if !in_single => {
+                    self.out.push(c);
+                    self.pos += 1;
+                    if let Some(&next) = self.chars.get(self.pos) {
+                        self.out.push(next);
+                        self.pos += 1;
+                    }
+                    continue;
+                }
+                '!' if !in_single && self.starts_reference(in_double) => {
+                    self.reference()?;
+                    continue;
+                }
+                _ => {}
+            }
+            self.out.push(c);
+            self.pos += 1;
+        }
+        Ok(())
+    }
+
+    /// Whether the `!` at the current position starts a history reference
+    fn starts_reference(&self, in_double: bool) -> bool {
+        match self.chars.get(self.pos + 1) {
+            None => false,
+            Some(c) if c.is_whitespace() || *c == '=' || *c == '(' => false,
+            Some('"') if in_double => false,
+            _ => {
+                // `$!` and `${!name}` are parameter expansions, and `[!...]`
+                // with a closing `]` later on the line is a glob negation
+                let before = &self.chars[..self.pos];
+                let negation =
+                    before.ends_with(&['[']) && self.chars[self.pos + 1..].contains(&']');
+                !(before.ends_with(&['$']) || before.ends_with(&['$', '{']) || negation)
+            }
+        }
+    }
+
+    /// The text read since `start`, for error messages
+    fn spec(&self, start: usize) -> String {
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    /// Replace the reference starting at the current `!`
+    fn reference(&mut self) -> ShellResult<()> {
+        let start = self.pos;
+        self.pos += 1;
+
+        let event = self.event(start)?;
+        let mut text = match self.word_designator(start, &split_words(&event))? {
+            Some(words) => words,
+            None => event,
+        };
+        text = self.modifiers(start, text)?;
+
+        self.out.push_str(&text);
+        self.expanded = true;
+        Ok(())
+    }
+
+    /// Find the history entry an event designator refers to
+    fn event(&mut self, start: usize) -> ShellResult<String> {
+        let entry = match self.chars.get(self.pos).copied() {
+            Some('!') => {
+                self.pos += 1;
+                self.relative(1)
+            }
+            Some(c) if c.is_ascii_digit() => {
+                let n = self.number();
+                n.checked_sub(1).and_then(|i| self.history.get(i)).cloned()
+            }
+            Some('-') if self.peek_digit(1) => {
+                self.pos += 1;
+                let n = self.number();
+                self.relative(n)
+            }
+            Some('?') => {
+                self.pos += 1;
+                let text = self.read_until(|c| c == '?' || c == '\n');
+                if self.chars.get(self.pos) == Some(&'?') {
+                    self.pos += 1;
+                }
+                self.search(|entry| !text.is_empty() && entry.contains(&text))
+            }
+            // `!#` is the line typed so far
+            Some('#') => {
+                self.pos += 1;
+                Some(self.out.clone())
+            }
+            // A word designator alone refers to the previous command
+            Some('^' | '$' | '*' | ':' | '-') => self.relative(1),
+            _ => {
+                let prefix = self.read_until(|c| EVENT_DELIMITERS.contains(c));
+                self.search(|entry| !prefix.is_empty() && entry.starts_with(&prefix))
+            }
+        };
+
+        entry.ok_or_else(|| {
+            ShellError::ExpansionError(format!("{}: event not found", self.spec(start)))
+        })
+    }
+
+    /// The entry `n` commands back
+    fn relative(&self, n: usize) -> Option<String> {
+        let index = self.history.len().checked_sub(n)?;
+        self.history.get(index).cloned()
+    }
+
+    /// The most recent entry matching `matches`
+    fn search(&self, matches: impl Fn(&str) -> bool) -> Option<String> {
+        self.history.iter().rev().find(|e| matches(e)).cloned()
+    }
+
+    /// Select words of the event with `:n`, `:x-y`, `:^`, `:$`, `:*`...
+    /// The `:` may be left out before `^`, `$`, `*` and `-`
+    fn word_designator(&mut self, start: usize, words: &[String]) -> ShellResult<Option<String>> {
+        match self.chars.get(self.pos).copied() {
+            Some(':') if self.peek_designator(1) => self.pos += 1,
+            Some('^' | '$' | '*' | '-') => {}
+            _ => return Ok(None),
+        }
+
+        let last = words.len().checked_sub(1);
+        let (from, to) = match self.chars.get(self.pos).copied() {
+            Some('^') => {
+                self.pos += 1;
+                (1, Some(1))
+            }
+            Some('$') => {
+                self.pos += 1;
+                (last.unwrap_or(0), last)
+            }
+            // `*` is every argument, which may be none
+            Some('*') => {
+                self.pos += 1;
+                return Ok(Some(words.get(1..).unwrap_or_default().join(" ")));
+            }
+            Some('-') => {
+                self.pos += 1;
+                (0, self.range_end(last))
+            }
+            _ => {
+                let from = self.number();
+                match self.chars.get(self.pos) {
+                    Some('*') => {
+                        self.pos += 1;
+                        (from, last)
+                    }
+                    Some('-') => {
+                        self.pos += 1;
+                        (from, self.range_end(last))
+                    }
+                    _ => (from, Some(from)),
+                }
+            }
+        };
+
+        match to {
+            Some(to) if from <= to && to < words.len() => Ok(Some(words[from..=to].join(" "))),
+            _ => Err(ShellError::ExpansionError(format!(
+                "{}: bad word specifier",
+                self.spec(start)
+            ))),
+        }
+    }
+
+    /// The end of an `x-y` range: a number, `$`, or nothing for all but the
+    /// last word
+    fn range_end(&mut self, last: Option<usize>) -> Option<usize> {
+        match self.chars.get(self.pos) {
+            Some(c) if c.is_ascii_digit() => Some(self.number()),
+            Some('$') => {
+                self.pos += 1;
+                last
+            }
+            _ => last?.checked_sub(1),
+        }
+    }
+
+    /// Apply `:h`, `:t`, `:r`, `:e`, `:p`, `:s/old/new/`, `:gs/old/new/`
+    /// and `:&` modifiers in order
+    fn modifiers(&mut self, start: usize, mut text: String) -> ShellResult<String> {
+        while self.chars.get(self.pos) == Some(&':') {
+            let Some(&modifier) = self.chars.get(self.pos + 1) else {
+                break;
+            };
+            let global = modifier == 'g';
+            let modifier = if global {
+                self.chars.get(self.pos + 2).copied().unwrap_or(' ')
+            } else {
+                modifier
+            };
+            self.pos += if global { 3 } else { 2 };
+
+            text = match modifier {
+                'h' => head(&text).to_string(),
+                't' => tail(&text).to_string(),
+                'r' => root(&text).to_string(),
+                'e' => extension(&text).to_string(),
+                'p' => {
+                    self.print_only = true;
+                    text
+                }
+                's' => {
+                    let (pattern, replacement) = self.read_substitution()?;
+                    substitute(&text, &pattern, &replacement, global)
+                        .ok_or_else(|| substitution_failed(self.spec(start)))?
+                }
+                '&' => {
+                    let (pattern, replacement) = last_substitution()?;
+                    substitute(&text, &pattern, &replacement, global)
+                        .ok_or_else(|| substitution_failed(self.spec(start)))?
+                }
+                c if c.is_ascii_alphanumeric() || global => {
+                    return Err(ShellError::ExpansionError(format!(
+                        "{}: unrecognized history modifier",
+                        self.spec(start)
+                    )));
+                }
+                // Not a modifier: leave the `:` in the line
+                _ => {
+                    self.pos -= 2;
+                    break;
+                }
+            };
+        }
+        Ok(text)
+    }
+
+    /// Read the `/old/new/` part of `:s`, with any delimiter. The last
+    /// delimiter may be left out at the end of the line
+    fn read_substitution(&mut self) -> ShellResult<(String, String)> {
+        let Some(&delimiter) = self.chars.get(self.pos) else {
+            return last_substitution();
+        };
+        self.pos += 1;
+        let pattern = self.read_delimited(delimiter);
+        let replacement = self.read_delimited(delimiter);
+        remember_substitution(pattern, replacement)
+    }
+
+    /// `^old^new^` at the start of a line: `!!:s^old^new^`
+    fn quick_substitution(&mut self) -> ShellResult<()> {
+        self.pos = 1;
+        let pattern = self.read_delimited('^');
+        let replacement = self.read_delimited('^');
+
+        let Some(event) = self.relative(1) else {
+            return Err(ShellError::ExpansionError(format!(
+                "{}: event not found",
+                self.spec(0)
+            )));
+        };
+        let (pattern, replacement) = remember_substitution(pattern, replacement)?;
+        let text = substitute(&event, &pattern, &replacement, false)
+            .ok_or_else(|| substitution_failed(self.spec(0)))?;
+
+        self.out.push_str(&text);
+        self.expanded = true;
+        Ok(())
+    }
+
+    /// Read up to an unescaped `delimiter` (which is consumed) or the end
+    /// of the line
+    fn read_delimited(&mut self, delimiter: char) -> String {
+        let mut text = String::new();
+        while let Some(&c) = self.chars.get(self.pos) {
+            if c == '\n' {
+                break;
+            }
+            self.pos += 1;
+            if c == delimiter {
+                break;
+            }
+            if c == '\\' && self.chars.get(self.pos) == Some(&delimiter) {
+                text.push(delimiter);
+                self.pos += 1;
+                continue;
+            }
+            text.push(c);
+        }
+        text
+    }
+
+    fn read_until(&mut self, stop: impl Fn(char) -> bool) -> String {
+        let mut text = String::new();
+        while let Some(&c) = self.chars.get(self.pos) {
+            if stop(c) {
+                break;
+            }
+            text.push(c);
+            self.pos += 1;
+        }
+        text
+    }
+
+    /// Read a decimal number; one too large to be valid reads as `usize::MAX`
+    fn number(&mut self) -> usize {
+        let digits = self.read_until(|c| !c.is_ascii_digit());
+        digits.parse().unwrap_or(usize::MAX)
+    }
+
+    fn peek_digit(&self, offset: usize) -> bool {
+        self.chars
+            .get(self.pos + offset)
+            .is_some_and(|c| c.is_ascii_digit())
+    }
+
+    fn peek_designator(&self, offset: usize) -> bool {
+        self.chars
+            .get(self.pos + offset)
+            .is_some_and(|c| c.is_ascii_digit() || "^$*-".contains(*c))
+    }
+}
+
+/// Split a history entry into words the way the parser would see them:
+/// quoted text stays in one word and operators are words of their own
+fn split_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut chars = line.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let mut word = String::new();
+        if OPERATOR_CHARS.contains(c) {
+            while let Some(c) = chars.next_if(|c| OPERATOR_CHARS.contains(*c)) {
+                word.push(c);
+            }
+            words.push(word);
+            continue;
+        }
+
+        let mut quote = None;
+        while let Some(&c) = chars.peek() {
+            if quote.is_none() && (c.is_whitespace() || OPERATOR_CHARS.contains(c)) {
+                break;
+            }
+            chars.next();
+            word.push(c);
+            match c {
+                '\\' if quote != Some('\'') => {
+                    if let Some(next) = chars.next() {
+                        word.push(next);
+                    }
+                }
+                '\'' | '"' if quote.is_none() => quote = Some(c),
+                c if quote == Some(c) => quote = None,
+                _ => {}
+            }
+        }
+        words.push(word);
+    }
+    words
+}
+
+/// Replace the first (or every) occurrence of `pattern`. In the
+/// replacement, `&` stands for the pattern and `\&` for a literal `&`.
+/// Returns None if the pattern doesn't occur
+fn substitute(text: &str, pattern: &str, replacement: &str, global: bool) -> Option<String> {
+    if pattern.is_empty() || !text.contains(pattern) {
+        return None;
+    }
+
+    let mut with = String::new();
+    let mut chars = replacement.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('&') => with.push('&'),
+                Some(next) => {
+                    with.push('\\');
+                    with.push(next);
+                }
+                None => with.push('\\'),
+            },
+            '&' => with.push_str(pattern),
+            _ => with.push(c),
+        }
+    }
+
+    Some(if global {
+        text.replace(pattern, &with)
+    } else {
+        text.replacen(pattern, &with, 1)
+    })
+}
+
+/// Record a substitution for later `:&`; an empty pattern reuses the last one
+fn remember_substitution(pattern: String, replacement: String) -> ShellResult<(String, String)> {
+    let pattern = if pattern.is_empty() {
+        last_substitution()?.0
+    } else {
+        pattern
+    };
+    if let Ok(mut last) = LAST_SUBSTITUTION.lock() {
+        *last = Some((pattern.clone(), replacement.clone()));
+    }
+    Ok((pattern, replacement))
+}
+
+fn last_substitution() -> ShellResult<(String, String)> {
+    LAST_SUBSTITUTION
+        .lock()
+        .ok()
+        .and_then(|last| last.clone())
+        .ok_or_else(|| ShellError::ExpansionError("no previous substitution".to_string()))
+}
+
+fn substitution_failed(spec: String) -> ShellError {
+    ShellError::ExpansionError(format!("{}: substitution failed", spec))
+}
+
+/// `:h`: remove the last pathname component
+fn head(text: &str) -> &str {
+    match text.rfind('/') {
+        Some(i) => &text[..i],
+        None => text,
+    }
+}
+
+/// `:t`: keep only the last pathname component
+fn tail(text: &str) -> &str {
+    match text.rfind('/') {
+        Some(i) => &text[i + 1..],
+        None => text,
+    }
+}
+
+/// Position of the `.` starting the suffix of the last pathname component
+fn suffix_start(text: &str) -> Option<usize> {
+    let dot = text.rfind('.')?;
+    match text.rfind('/') {
+        Some(slash) if slash > dot => None,
+        _ => Some(dot),
+    }
+}
+
+/// `:r`: remove a trailing `.suffix`
+fn root(text: &str) -> &str {
+    match suffix_start(text) {
+        Some(i) => &text[..i],
+        None => text,
+    }
+}
+
+/// `:e`: keep only the trailing `.suffix`
+fn extension(text: &str) -> &str {
+    match suffix_start(text) {
+        Some(i) => &text[i..],
+        None => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history() -> Vec<String> {
+        vec![
+            "ls /usr/lib".to_string(),
+            "cp src/main.rs /tmp/backup/main.rs.bak".to_string(),
+            "echo 'one two' three".to_string(),
+        ]
+    }
+
+    fn expand_line(line: &str) -> String {
+        expand(line, &history()).unwrap().line
+    }
+
+    #[test]
+    fn events() {
+        assert_eq!(expand_line("sudo !!"), "sudo echo 'one two' three");
+        assert_eq!(expand_line("!1"), "ls /usr/lib");
+        assert_eq!(expand_line("!-2"), "cp src/main.rs /tmp/backup/main.rs.bak");
+        assert_eq!(expand_line("!ls -a"), "ls /usr/lib -a");
+        assert_eq!(
+            expand_line("!?main?"),
+            "cp src/main.rs /tmp/backup/main.rs.bak"
+        );
+        assert!(expand("!nope", &history()).is_err());
+        assert!(expand("!9", &history()).is_err());
+        assert!(expand("!!", &[]).is_err());
+    }
+
+    #[test]
+    fn word_designators() {
+        assert_eq!(expand_line("echo !$"), "echo three");
+        assert_eq!(expand_line("echo !^"), "echo 'one two'");
+        assert_eq!(expand_line("echo !!:0"), "echo echo");
+        assert_eq!(
+            expand_line("echo !-2:1-2"),
+            "echo src/main.rs /tmp/backup/main.rs.bak"
+        );
+        assert_eq!(expand_line("echo !!:*"), "echo 'one two' three");
+        assert_eq!(expand_line("echo !-2:0-"), "echo cp src/main.rs");
+        assert!(expand("echo !!:5", &history()).is_err());
+    }
+
+    #[test]
+    fn modifiers() {
+        let last = vec!["vi /tmp/backup/main.rs.bak".to_string()];
+        let expand_last = |line: &str| expand(line, &last).unwrap();
+        assert_eq!(expand_last("cd !$:h").line, "cd /tmp/backup");
+        assert_eq!(expand_last("echo !$:t").line, "echo main.rs.bak");
+        assert_eq!(expand_last("echo !$:r").line, "echo /tmp/backup/main.rs");
+        assert_eq!(expand_last("echo !$:e").line, "echo .bak");
+        assert_eq!(expand_last("echo !$:t:r:r").line, "echo main");
+        assert_eq!(
+            expand_last("!!:s/vi/cat/").line,
+            "cat /tmp/backup/main.rs.bak"
+        );
+        assert_eq!(expand_last("!!:gs/a/A/").line, "vi /tmp/bAckup/mAin.rs.bAk");
+        assert!(expand("!!:s/nope/x/", &last).is_err());
+        assert!(expand("!!:z", &last).is_err());
+
+        let printed = expand_last("!!:p");
+        assert!(printed.print_only);
+        assert_eq!(printed.line, "vi /tmp/backup/main.rs.bak");
+    }
+
+    #[test]
+    fn quick_substitution() {
+        assert_eq!(expand_line("^three^four"), "echo 'one two' four");
+        assert_eq!(expand_line("^two^2^ more"), "echo 'one 2' three more");
+        assert!(expand("^nope^x", &history()).is_err());
+    }
+
+    #[test]
+    fn literal_exclamation_marks() {
+        for line in [
+            "echo '!!'",
+            "echo \\!!",
+            "echo \"hi!\"",
+            "[ a != b ]",
+            "! true",
+            "echo $! ${!name}",
+            "echo g/[!a].rs",
+            "ls [!0-9]*",
+            "echo done!",
+        ] {
+            let expansion = expand(line, &history()).unwrap();
+            assert!(!expansion.expanded, "{}", line);
+            assert_eq!(expansion.line, line);
+        }
+        assert_eq!(expand_line("echo \"!!\""), "echo \"echo 'one two' three\"");
+        // Without a closing `]`, the `!` after `[` is an event
+        assert_eq!(expand_line("echo [!ls"), "echo [ls /usr/lib");
+    }
+}
@@ -1,30 +1,45 @@
+pub mod arithmetic;
 pub mod commands;
 pub mod completion;
+pub mod control;
 pub mod errors;
+pub mod executor;
+pub mod expansion;
+pub mod functions;
 pub mod history;
+pub mod history_expansion;
+pub mod options;
+pub mod parser;
 pub mod pipeline;
+pub mod process;
 pub mod redirection;
 pub mod repl;
+pub mod status;
+pub mod variables;
 
-use errors::{ShellError, ShellResult};
-use repl::{handle_command_input, read_input};
+use errors::{report_error, ShellError, ShellResult};
+use repl::{handle_command_input, Session};
+use std::time::Instant;
 
 /// Main entry point for the shell REPL
 pub fn run_shell() -> ShellResult<()> {
+    history::init();
+    let mut session = Session::new()?;
+
     loop {
-        match read_input() {
+        match session.read_input() {
             Ok(input) => {
                 // Add command to history before executing
-                history::add_to_history(&input);
+                let started = Instant::now();
+                let recorded = history::add_to_history(&input);
 
                 if let Err(e) = handle_command_input(&input) {
-                    match e {
-                        ShellError::CommandNotFound(msg) => eprintln!("{}", msg),
-                        ShellError::InvalidDirectory(msg) => eprintln!("{}", msg),
-                        ShellError::ExecutionError(msg) => eprintln!("{}", msg),
-                        ShellError::RedirectionError(msg) => eprintln!("{}", msg),
-                        _ => eprintln!("Error: {}", e),
-                    }
+                    report_error(&e);
+                    status::set_last_status(e.exit_status());
+                }
+
+                if recorded {
+                    history::finish_command(&input, status::last_status(), started.elapsed());
                 }
             }
             Err(ShellError::Interrupted) => {
@@ -36,5 +51,7 @@ pub fn run_shell() -> ShellResult<()> {
             }
         }
     }
+
+    history::save_on_exit();
     Ok(())
 }
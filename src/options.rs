@@ -0,0 +1,69 @@
+use once_cell::sync::Lazy;
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+/// Which builtin toggles an option
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OptionKind {
+    /// `shopt -s` / `shopt -u`
+    Shopt,
+    /// `set -o` / `set +o`
+    Set,
+}
+
+/// All known shell options, sorted by name
+pub const OPTIONS: &[(&str, OptionKind)] = &[
+    ("dotglob", OptionKind::Shopt),
+    ("failglob", OptionKind::Shopt),
+    ("globstar", OptionKind::Shopt),
+    ("noclobber", OptionKind::Set),
+    ("nullglob", OptionKind::Shopt),
+];
+
+/// Names of the options currently enabled
+static ENABLED: Lazy<Mutex<HashSet<&'static str>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// Look up an option of the given kind by name
+fn find_option(name: &str, kind: OptionKind) -> Option<&'static str> {
+    OPTIONS
+        .iter()
+        .find(|(n, k)| *n == name && *k == kind)
+        .map(|(n, _)| *n)
+}
+
+/// Check whether an option is enabled
+pub fn is_enabled(name: &str) -> bool {
+    ENABLED
+        .lock()
+        .map(|set| set.contains(name))
+        .unwrap_or(false)
+}
+
+/// Enable or disable an option. Returns false if there is no such option
+pub fn set_enabled(name: &str, kind: OptionKind, enabled: bool) -> bool {
+    let Some(name) = find_option(name, kind) else {
+        return false;
+    };
+    if let Ok(mut set) = ENABLED.lock() {
+        if enabled {
+            set.insert(name);
+        } else {
+            set.remove(name);
+        }
+    }
+    true
+}
+
+/// Names and states of all options of one kind
+pub fn list_options(kind: OptionKind) -> Vec<(&'static str, bool)> {
+    OPTIONS
+        .iter()
+        .filter(|(_, k)| *k == kind)
+        .map(|(name, _)| (*name, is_enabled(name)))
+        .collect()
+}
+
+/// Check whether a name is an option of the given kind
+pub fn is_option(name: &str, kind: OptionKind) -> bool {
+    find_option(name, kind).is_some()
+}
@@ -0,0 +1,183 @@
+/// A single shell word exactly as it appeared in the input (quotes included).
+/// Quote removal and expansions happen later, at execution time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Word(pub String);
+
+impl Word {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Redirection operator
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RedirectOp {
+    /// `>`
+    Output,
+    /// `>>`
+    Append,
+    /// `>|`, overwrites even with noclobber set
+    Clobber,
+    /// `<`
+    Input,
+    /// `<>`
+    ReadWrite,
+    /// `>&`
+    DupOutput,
+    /// `<&`
+    DupInput,
+    /// `&>`
+    OutputAll,
+    /// `&>>`
+    AppendAll,
+    /// `<<< word`
+    HereString,
+    /// `<<DELIM`; the redirect target holds the body
+    HereDoc {
+        /// Whether parameters and substitutions in the body are expanded
+        expand: bool,
+    },
+}
+
+impl RedirectOp {
+    /// Whether the operator redirects standard input by default
+    pub fn is_input(self) -> bool {
+        matches!(
+            self,
+            RedirectOp::Input
+                | RedirectOp::ReadWrite
+                | RedirectOp::DupInput
+                | RedirectOp::HereString
+                | RedirectOp::HereDoc { .. }
+        )
+    }
+}
+
+/// A redirection attached to a command, e.g. `2>> errors.log`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Redirect {
+    /// Explicit file descriptor number written before the operator, if any
+    pub fd: Option<u32>,
+    pub op: RedirectOp,
+    pub target: Word,
+}
+
+/// A `NAME=value` assignment preceding a command
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assignment {
+    pub name: String,
+    pub value: Word,
+}
+
+/// A command name with its arguments and redirections
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SimpleCommand {
+    pub assignments: Vec<Assignment>,
+    pub words: Vec<Word>,
+    pub redirects: Vec<Redirect>,
+}
+
+/// A command built from other commands
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompoundCommand {
+    /// `( list )`, run in a forked copy of the shell
+    Subshell(List),
+    /// `{ list; }`, run in the current shell
+    Group(List),
+    /// `if list; then list; [elif list; then list;]... [else list;] fi`
+    If {
+        /// Each condition with the body run when it succeeds
+        branches: Vec<(List, List)>,
+        else_body: Option<List>,
+    },
+    /// `while list; do list; done`, or `until` when `until` is set
+    While {
+        condition: List,
+        body: List,
+        until: bool,
+    },
+    /// `for name [in words]; do list; done`
+    For {
+        name: String,
+        /// The words to iterate over; `None` means the positional parameters
+        words: Option<Vec<Word>>,
+        body: List,
+    },
+    /// `for (( init; condition; step )); do list; done`
+    ArithFor {
+        init: String,
+        condition: String,
+        step: String,
+        body: List,
+    },
+    /// `case word in pattern) list ;; ... esac`
+    Case { word: Word, items: Vec<CaseItem> },
+}
+
+/// How a `case` item ends
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CaseTerminator {
+    /// `;;`: stop after this item
+    Break,
+    /// `;&`: run the next item's body without testing its patterns
+    FallThrough,
+    /// `;;&`: test the patterns of the following items
+    Continue,
+}
+
+/// One `pattern | pattern) list` item of a `case` command
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaseItem {
+    pub patterns: Vec<Word>,
+    pub body: List,
+    pub terminator: CaseTerminator,
+}
+
+/// A single stage of a pipeline
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Simple(SimpleCommand),
+    /// `(( expression ))`, succeeds when the expression is non-zero
+    Arithmetic(String),
+    /// A compound command with redirections applied to all of it
+    Compound(CompoundCommand, Vec<Redirect>),
+    /// `name() compound-command`, defines a shell function
+    Function(FunctionDef),
+}
+
+/// A shell function: a compound command run with its own positional parameters
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionDef {
+    pub name: String,
+    pub body: CompoundCommand,
+    /// Redirections applied each time the function runs
+    pub redirects: Vec<Redirect>,
+}
+
+/// One or more commands connected with `|`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pipeline {
+    pub commands: Vec<Command>,
+}
+
+/// Connector between pipelines of an AND-OR list
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AndOrOp {
+    /// `&&`: run the next pipeline only if the previous one succeeded
+    And,
+    /// `||`: run the next pipeline only if the previous one failed
+    Or,
+}
+
+/// Pipelines joined with `&&` / `||`, evaluated left to right
+#[derive(Debug, Clone, PartialEq)]
+pub struct AndOr {
+    pub first: Pipeline,
+    pub rest: Vec<(AndOrOp, Pipeline)>,
+}
+
+/// A sequence of AND-OR lists separated by `;` or newlines
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct List {
+    pub items: Vec<AndOr>,
+}
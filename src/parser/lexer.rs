@@ -0,0 +1,606 @@
+use crate::errors::{ShellError, ShellResult};
+use crate::expansion::{find_closing_backquote, find_closing_brace, find_closing_paren};
+use std::fmt;
+
+/// Tokens produced by the lexer
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    /// A word with its quoting preserved
+    Word(String),
+    /// A file descriptor number directly preceding a redirection operator
+    IoNumber(u32),
+    /// The expression of an `(( ... ))` arithmetic command
+    Arithmetic(String),
+    Pipe,
+    AndIf,
+    OrIf,
+    Semi,
+    /// `;;`, ends a `case` item
+    DSemi,
+    /// `;&`, falls through to the next `case` item
+    SemiAnd,
+    /// `;;&`, goes on testing the next `case` patterns
+    DSemiAnd,
+    Newline,
+    LParen,
+    RParen,
+    Great,
+    DGreat,
+    /// `>|`
+    Clobber,
+    Less,
+    /// `<<<`
+    TLess,
+    /// `>&`
+    GreatAnd,
+    /// `<&`
+    LessAnd,
+    /// `<>`
+    LessGreat,
+    /// `&>`
+    AndGreat,
+    /// `&>>`
+    AndDGreat,
+    /// A `<<DELIM` or `<<-DELIM` here-document with its body already read
+    HereDoc {
+        body: String,
+        /// Whether the body is subject to expansion (unquoted delimiter)
+        expand: bool,
+    },
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(w) => write!(f, "{}", w),
+            Token::IoNumber(n) => write!(f, "{}", n),
+            Token::Arithmetic(expr) => write!(f, "(({}))", expr),
+            Token::Pipe => write!(f, "|"),
+            Token::AndIf => write!(f, "&&"),
+            Token::OrIf => write!(f, "||"),
+            Token::Semi => write!(f, ";"),
+            Token::DSemi => write!(f, ";;"),
+            Token::SemiAnd => write!(f, ";&"),
+            Token::DSemiAnd => write!(f, ";;&"),
+            Token::Newline => write!(f, "newline"),
+            Token::LParen => write!(f, "("),
+            Token::RParen => write!(f, ")"),
+            Token::Great => write!(f, ">"),
+            Token::DGreat => write!(f, ">>"),
+            Token::Clobber => write!(f, ">|"),
+            Token::Less => write!(f, "<"),
+            Token::TLess => write!(f, "<<<"),
+            Token::GreatAnd => write!(f, ">&"),
+            Token::LessAnd => write!(f, "<&"),
+            Token::LessGreat => write!(f, "<>"),
+            Token::AndGreat => write!(f, "&>"),
+            Token::AndDGreat => write!(f, "&>>"),
+            Token::HereDoc { .. } => write!(f, "<<"),
+        }
+    }
+}
+
+/// A here-document whose body starts after the next newline
+struct PendingHereDoc {
+    /// Index of the `Token::HereDoc` to fill in
+    token: usize,
+    delimiter: String,
+    strip_tabs: bool,
+}
+
+/// Quote-aware tokenizer for shell input
+pub struct Lexer {
+    chars: Vec<char>,
+    pos: usize,
+    pending_heredocs: Vec<PendingHereDoc>,
+}
+
+impl Lexer {
+    pub fn new(input: &str) -> Self {
+        Self {
+            chars: input.chars().collect(),
+            pos: 0,
+            pending_heredocs: Vec::new(),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    /// Split the whole input into tokens
+    pub fn tokenize(mut self) -> ShellResult<Vec<Token>> {
+        let mut tokens = Vec::new();
+
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\t' => self.pos += 1,
+                '\\' if self.peek_at(1) == Some('\n') => {
+                    // Line continuation between words
+                    self.pos += 2;
+                }
+                '#' => {
+                    // Comment runs to the end of the line
+                    while let Some(c) = self.peek() {
+                        if c == '\n' {
+                            break;
+                        }
+                        self.pos += 1;
+                    }
+                }
+                '\n' => {
+                    self.pos += 1;
+                    tokens.push(Token::Newline);
+                    self.read_heredoc_bodies(&mut tokens)?;
+                }
+                '|' if self.peek_at(1) == Some('|') => {
+                    self.pos += 2;
+                    tokens.push(Token::OrIf);
+                }
+                '|' => {
+                    self.pos += 1;
+                    tokens.push(Token::Pipe);
+                }
+                '&' if self.peek_at(1) == Some('&') => {
+                    self.pos += 2;
+                    tokens.push(Token::AndIf);
+                }
+                ';' => tokens.push(self.read_semi()),
+                '>' if self.peek_at(1) != Some('(') => tokens.push(self.read_operator()),
+                '&' if self.peek_at(1) == Some('>') => tokens.push(self.read_operator()),
+                '<' if self.peek_at(1) == Some('<') && self.peek_at(2) != Some('<') => {
+                    let token = self.read_heredoc_operator(tokens.len())?;
+                    tokens.push(token);
+                }
+                '<' if self.peek_at(1) != Some('(') => tokens.push(self.read_operator()),
+                '(' if self.peek_at(1) == Some('(') => tokens.push(self.read_arithmetic()?),
+                '(' => {
+                    self.pos += 1;
+                    tokens.push(Token::LParen);
+                }
+                ')' => {
+                    self.pos += 1;
+                    tokens.push(Token::RParen);
+                }
+                _ => {
+                    let word = self.read_word()?;
+
+                    // A bare number immediately followed by a redirection is a fd number
+                    let is_io_number = !word.is_empty()
+                        && word.chars().all(|c| c.is_ascii_digit())
+                        && matches!(self.peek(), Some('>') | Some('<'));
+                    match word.parse() {
+                        Ok(fd) if is_io_number => tokens.push(Token::IoNumber(fd)),
+                        _ => tokens.push(Token::Word(word)),
+                    }
+                }
+            }
+        }
+
+        // A here-document body must follow before the end of input
+        if let Some(pending) = self.pending_heredocs.first() {
+            return Err(ShellError::IncompleteInput(format!(
+                "here-document delimited by end-of-file (wanted `{}')",
+                pending.delimiter
+            )));
+        }
+
+        Ok(tokens)
+    }
+
+    /// Read a redirection operator starting at the current position
+    fn read_operator(&mut self) -> Token {
+        let c = self.chars[self.pos];
+        self.pos += 1;
+        let (token, len) = match (c, self.peek(), self.peek_at(1)) {
+            ('>', Some('>'), _) => (Token::DGreat, 1),
+            ('>', Some('&'), _) => (Token::GreatAnd, 1),
+            ('>', Some('|'), _) => (Token::Clobber, 1),
+            ('>', _, _) => (Token::Great, 0),
+            ('&', Some('>'), Some('>')) => (Token::AndDGreat, 2),
+            ('&', _, _) => (Token::AndGreat, 1),
+            (_, Some('<'), _) => (Token::TLess, 2),
+            (_, Some('&'), _) => (Token::LessAnd, 1),
+            (_, Some('>'), _) => (Token::LessGreat, 1),
+            _ => (Token::Less, 0),
+        };
+        self.pos += len;
+        token
+    }
+
+    /// Read `;` or one of the `case` item terminators `;;`, `;&` and `;;&`
+    fn read_semi(&mut self) -> Token {
+        self.pos += 1;
+        let (token, len) = match (self.peek(), self.peek_at(1)) {
+            (Some(';'), Some('&')) => (Token::DSemiAnd, 2),
+            (Some(';'), _) => (Token::DSemi, 1),
+            (Some('&'), _) => (Token::SemiAnd, 1),
+            _ => (Token::Semi, 0),
+        };
+        self.pos += len;
+        token
+    }
+
+    /// Read `<<DELIM` or `<<-DELIM`. The body is filled in once the end of
+    /// the line is reached; `index` is where the token will be stored
+    fn read_heredoc_operator(&mut self, index: usize) -> ShellResult<Token> {
+        self.pos += 2;
+        let strip_tabs = self.peek() == Some('-');
+        if strip_tabs {
+            self.pos += 1;
+        }
+        while matches!(self.peek(), Some(' ') | Some('\t')) {
+            self.pos += 1;
+        }
+
+        let word = match self.peek() {
+            Some(c) if !matches!(c, '\n' | '|' | ';' | '&' | '<' | '>') => self.read_word()?,
+            Some(c) => {
+                let token = if c == '\n' {
+                    "newline".to_string()
+                } else {
+                    c.to_string()
+                };
+                return Err(ShellError::SyntaxError(format!(
+                    "syntax error near unexpected token `{}'",
+                    token
+                )));
+            }
+            None => {
+                return Err(ShellError::SyntaxError(
+                    "syntax error near unexpected token `newline'".to_string(),
+                ));
+            }
+        };
+
+        // Any quoting in the delimiter disables expansion of the body
+        let expand = !word.contains(['\'', '"', '\\']);
+        self.pending_heredocs.push(PendingHereDoc {
+            token: index,
+            delimiter: remove_quotes(&word),
+            strip_tabs,
+        });
+
+        Ok(Token::HereDoc {
+            body: String::new(),
+            expand,
+        })
+    }
+
+    /// Read the bodies of the here-documents started on the line just ended
+    fn read_heredoc_bodies(&mut self, tokens: &mut [Token]) -> ShellResult<()> {
+        for pending in std::mem::take(&mut self.pending_heredocs) {
+            let mut text = String::new();
+            let mut terminated = false;
+
+            while self.pos < self.chars.len() {
+                let end = (self.pos..self.chars.len())
+                    .find(|&i| self.chars[i] == '\n')
+                    .unwrap_or(self.chars.len());
+                let mut line: String = self.chars[self.pos..end].iter().collect();
+                self.pos = (end + 1).min(self.chars.len());
+
+                if pending.strip_tabs {
+                    line = line.trim_start_matches('\t').to_string();
+                }
+                if line == pending.delimiter {
+                    terminated = true;
+                    break;
+                }
+                text.push_str(&line);
+                text.push('\n');
+            }
+
+            if !terminated {
+                return Err(ShellError::IncompleteInput(format!(
+                    "here-document delimited by end-of-file (wanted `{}')",
+                    pending.delimiter
+                )));
+            }
+            if let Token::HereDoc { body, .. } = &mut tokens[pending.token] {
+                *body = text;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read a word, keeping quotes and escapes intact for later expansion
+    fn read_word(&mut self) -> ShellResult<String> {
+        let mut word = String::new();
+
+        while let Some(c) = self.peek() {
+            match c {
+                '<' | '>' if self.peek_at(1) == Some('(') => {
+                    self.read_process_substitution(&mut word)?
+                }
+                ' ' | '\t' | '\n' | '|' | ';' | '>' | '<' | '(' | ')' => break,
+                '&' if matches!(self.peek_at(1), Some('&') | Some('>')) => break,
+                '\\' => {
+                    self.pos += 1;
+                    match self.peek() {
+                        // Escaped newline is a line continuation, drop both characters
+                        Some('\n') => self.pos += 1,
+                        Some(next) => {
+                            word.push('\\');
+                            word.push(next);
+                            self.pos += 1;
+                        }
+                        // A trailing backslash continues on the next line
+                        None => return Err(unexpected_eof()),
+                    }
+                }
+                '$' if matches!(self.peek_at(1), Some('{') | Some('(')) => {
+                    self.read_dollar_expression(&mut word)?
+                }
+                '`' => self.read_backquoted(&mut word)?,
+                '\'' => {
+                    word.push(c);
+                    self.pos += 1;
+                    loop {
+                        match self.peek() {
+                            Some('\'') => {
+                                word.push('\'');
+                                self.pos += 1;
+                                break;
+                            }
+                            Some(ch) => {
+                                word.push(ch);
+                                self.pos += 1;
+                            }
+                            None => return Err(unmatched_quote(c)),
+                        }
+                    }
+                }
+                '"' => {
+                    word.push(c);
+                    self.pos += 1;
+                    loop {
+                        match self.peek() {
+                            Some('"') => {
+                                word.push('"');
+                                self.pos += 1;
+                                break;
+                            }
+                            Some('\\') => {
+                                word.push('\\');
+                                self.pos += 1;
+                                if let Some(next) = self.peek() {
+                                    word.push(next);
+                                    self.pos += 1;
+                                }
+                            }
+                            Some('$') if matches!(self.peek_at(1), Some('{') | Some('(')) => {
+                                self.read_dollar_expression(&mut word)?
+                            }
+                            Some('`') => self.read_backquoted(&mut word)?,
+                            Some(ch) => {
+                                word.push(ch);
+                                self.pos += 1;
+                            }
+                            None => return Err(unmatched_quote(c)),
+                        }
+                    }
+                }
+                _ => {
+                    word.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+
+        Ok(word)
+    }
+
+    /// Copy a `${...}` or `$(...)` expansion verbatim, including nested
+    /// brackets and quotes
+    fn read_dollar_expression(&mut self, word: &mut String) -> ShellResult<()> {
+        let end = match self.peek_at(1) {
+            Some('{') => find_closing_brace(&self.chars, self.pos + 2),
+            _ => find_closing_paren(&self.chars, self.pos + 2),
+        }
+        .ok_or_else(unexpected_eof)?;
+        word.extend(&self.chars[self.pos..=end]);
+        self.pos = end + 1;
+        Ok(())
+    }
+
+    /// Copy a `<(...)` or `>(...)` process substitution verbatim
+    fn read_process_substitution(&mut self, word: &mut String) -> ShellResult<()> {
+        let end = find_closing_paren(&self.chars, self.pos + 2).ok_or_else(unexpected_eof)?;
+        word.extend(&self.chars[self.pos..=end]);
+        self.pos = end + 1;
+        Ok(())
+    }
+
+    /// Read an `(( ... ))` arithmetic command
+    fn read_arithmetic(&mut self) -> ShellResult<Token> {
+        let end = find_closing_paren(&self.chars, self.pos + 1).ok_or_else(unexpected_eof)?;
+        // The inner parenthesis must close right before the outer one
+        if self.chars[end - 1] != ')'
+            || find_closing_paren(&self.chars, self.pos + 2) != Some(end - 1)
+        {
+            return Err(ShellError::SyntaxError(
+                "syntax error near unexpected token `('".to_string(),
+            ));
+        }
+        let expr = self.chars[self.pos + 2..end - 1].iter().collect();
+        self.pos = end + 1;
+        Ok(Token::Arithmetic(expr))
+    }
+
+    /// Copy a backquoted command substitution verbatim
+    fn read_backquoted(&mut self, word: &mut String) -> ShellResult<()> {
+        let end = find_closing_backquote(&self.chars, self.pos + 1).ok_or_else(unexpected_eof)?;
+        word.extend(&self.chars[self.pos..=end]);
+        self.pos = end + 1;
+        Ok(())
+    }
+}
+
+/// Quote removal for a here-document delimiter
+fn remove_quotes(word: &str) -> String {
+    let mut result = String::new();
+    let mut chars = word.chars();
+    let mut quote = None;
+
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\\', None) => result.extend(chars.next()),
+            ('\'' | '"', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// The input ended in the middle of a construct; more lines may complete it
+fn unexpected_eof() -> ShellError {
+    ShellError::IncompleteInput("syntax error: unexpected end of file".to_string())
+}
+
+/// The input ended inside a quoted string
+fn unmatched_quote(quote: char) -> ShellError {
+    ShellError::IncompleteInput(format!(
+        "unexpected EOF while looking for matching `{}'",
+        quote
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokenize(input: &str) -> Vec<Token> {
+        Lexer::new(input).tokenize().unwrap()
+    }
+
+    fn word(text: &str) -> Token {
+        Token::Word(text.to_string())
+    }
+
+    #[test]
+    fn quotes_keep_operators_in_words() {
+        assert_eq!(
+            tokenize(r#"echo 'a|b' "x|y" a\|b | wc -l"#),
+            [
+                word("echo"),
+                word("'a|b'"),
+                word(r#""x|y""#),
+                word(r"a\|b"),
+                Token::Pipe,
+                word("wc"),
+                word("-l"),
+            ]
+        );
+        assert_eq!(
+            tokenize(r#"echo "a;b"'c&&d'e"#),
+            [word("echo"), word(r#""a;b"'c&&d'e"#)]
+        );
+    }
+
+    #[test]
+    fn comments_and_line_continuations() {
+        assert_eq!(
+            tokenize("a|b\nc # comment | d"),
+            [word("a"), Token::Pipe, word("b"), Token::Newline, word("c")]
+        );
+        assert_eq!(tokenize("echo a#b"), [word("echo"), word("a#b")]);
+        assert_eq!(tokenize("echo a\\\nb"), [word("echo"), word("ab")]);
+    }
+
+    #[test]
+    fn io_numbers() {
+        assert_eq!(
+            tokenize("cmd 2>&1 3>f 4 >g a5>h"),
+            [
+                word("cmd"),
+                Token::IoNumber(2),
+                Token::GreatAnd,
+                word("1"),
+                Token::IoNumber(3),
+                Token::Great,
+                word("f"),
+                word("4"),
+                Token::Great,
+                word("g"),
+                word("a5"),
+                Token::Great,
+                word("h"),
+            ]
+        );
+        assert_eq!(
+            tokenize("cmd 0<in 2>>log"),
+            [
+                word("cmd"),
+                Token::IoNumber(0),
+                Token::Less,
+                word("in"),
+                Token::IoNumber(2),
+                Token::DGreat,
+                word("log"),
+            ]
+        );
+    }
+
+    #[test]
+    fn heredoc_bodies() {
+        assert_eq!(
+            tokenize("cat <<EOF | wc\nline $x\n\tEOF\nEOF\necho done"),
+            [
+                word("cat"),
+                Token::HereDoc {
+                    body: "line $x\n\tEOF\n".to_string(),
+                    expand: true,
+                },
+                Token::Pipe,
+                word("wc"),
+                Token::Newline,
+                word("echo"),
+                word("done"),
+            ]
+        );
+        assert_eq!(
+            tokenize("cat <<-'EOF' 2<<\"E\"\n\tone\n\tEOF\ntwo\nE\n"),
+            [
+                word("cat"),
+                Token::HereDoc {
+                    body: "one\n".to_string(),
+                    expand: false,
+                },
+                Token::IoNumber(2),
+                Token::HereDoc {
+                    body: "two\n".to_string(),
+                    expand: false,
+                },
+                Token::Newline,
+            ]
+        );
+    }
+
+    #[test]
+    fn unfinished_input_is_incomplete() {
+        for input in [
+            "echo 'abc",
+            "echo \"abc",
+            "echo $(ls",
+            "echo `ls",
+            "echo \\",
+            "cat <<EOF",
+            "cat <<EOF\nno end",
+        ] {
+            assert!(
+                matches!(
+                    Lexer::new(input).tokenize(),
+                    Err(ShellError::IncompleteInput(_))
+                ),
+                "{}",
+                input
+            );
+        }
+    }
+}
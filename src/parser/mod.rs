@@ -0,0 +1,774 @@
+pub mod ast;
+pub mod lexer;
+pub mod printer;
+
+use crate::errors::{ShellError, ShellResult};
+use crate::variables::is_valid_name;
+use ast::{
+    AndOr, AndOrOp, Assignment, CaseItem, CaseTerminator, Command, CompoundCommand, FunctionDef,
+    List, Pipeline, Redirect, RedirectOp, SimpleCommand, Word,
+};
+use lexer::{Lexer, Token};
+
+/// Parse a line of input into a command list
+pub fn parse(input: &str) -> ShellResult<List> {
+    let tokens = Lexer::new(input).tokenize()?;
+    Parser::new(tokens).parse_program()
+}
+
+/// Check whether the input stops in the middle of a construct (an open
+/// quote, `if` or `{`, a trailing `|` or `&&`, a pending here-document...)
+/// and more lines should be read before running it
+pub fn is_incomplete(input: &str) -> bool {
+    matches!(parse(input), Err(ShellError::IncompleteInput(_)))
+}
+
+/// Reserved words that end a nested list when found in command position
+const CLOSING_WORDS: &[&str] = &["}", "then", "elif", "else", "fi", "do", "done", "esac"];
+
+/// Reserved words that start a compound command
+const OPENING_WORDS: &[&str] = &["{", "if", "while", "until", "for", "case"];
+
+/// Recursive-descent parser over the token stream
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn new(tokens: Vec<Token>) -> Self {
+        Self { tokens, pos: 0 }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        if token.is_some() {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.peek() == Some(&Token::Newline) {
+            self.pos += 1;
+        }
+    }
+
+    /// Build a syntax error for the token at the current position. Running
+    /// out of tokens means the command continues on another line
+    fn unexpected(&self) -> ShellError {
+        match self.peek() {
+            Some(token) => {
+                ShellError::SyntaxError(format!("syntax error near unexpected token `{}'", token))
+            }
+            None => ShellError::IncompleteInput("syntax error: unexpected end of file".to_string()),
+        }
+    }
+
+    /// program := list EOF
+    fn parse_program(&mut self) -> ShellResult<List> {
+        let list = self.parse_list()?;
+        if self.peek().is_some() {
+            return Err(self.unexpected());
+        }
+        Ok(list)
+    }
+
+    /// Whether the current token ends a list: end of input, `)`, a `case`
+    /// item terminator or a closing reserved word such as `}`
+    fn at_list_end(&self) -> bool {
+        match self.peek() {
+            None
+            | Some(Token::RParen)
+            | Some(Token::DSemi)
+            | Some(Token::SemiAnd)
+            | Some(Token::DSemiAnd) => true,
+            Some(Token::Word(w)) => CLOSING_WORDS.contains(&w.as_str()),
+            _ => false,
+        }
+    }
+
+    /// Whether the current token starts a redirection
+    fn at_redirect(&self) -> bool {
+        matches!(
+            self.peek(),
+            Some(Token::IoNumber(_))
+                | Some(Token::Great)
+                | Some(Token::DGreat)
+                | Some(Token::Clobber)
+                | Some(Token::Less)
+                | Some(Token::TLess)
+                | Some(Token::GreatAnd)
+                | Some(Token::LessAnd)
+                | Some(Token::LessGreat)
+                | Some(Token::AndGreat)
+                | Some(Token::AndDGreat)
+                | Some(Token::HereDoc { .. })
+        )
+    }
+
+    /// Whether the current token is the word `word`
+    fn at_word(&self, word: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(w)) if w == word)
+    }
+
+    /// Consume the reserved word `word`, or fail with a syntax error
+    fn expect_word(&mut self, word: &str) -> ShellResult<()> {
+        match self.peek() {
+            Some(Token::Word(w)) if w == word => {
+                self.advance();
+                Ok(())
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    /// list := and_or ((';' | NEWLINE) and_or)* [';']
+    fn parse_list(&mut self) -> ShellResult<List> {
+        let mut list = List::default();
+
+        loop {
+            self.skip_newlines();
+            if self.at_list_end() {
+                break;
+            }
+
+            list.items.push(self.parse_and_or()?);
+
+            match self.peek() {
+                Some(Token::Semi) | Some(Token::Newline) => {
+                    self.advance();
+                }
+                _ => break,
+            }
+        }
+
+        Ok(list)
+    }
+
+    /// and_or := pipeline (('&&' | '||') NEWLINE* pipeline)*
+    fn parse_and_or(&mut self) -> ShellResult<AndOr> {
+        let first = self.parse_pipeline()?;
+        let mut rest = Vec::new();
+
+        loop {
+            let op = match self.peek() {
+                Some(Token::AndIf) => AndOrOp::And,
+                Some(Token::OrIf) => AndOrOp::Or,
+                _ => break,
+            };
+            self.advance();
+            self.skip_newlines();
+            rest.push((op, self.parse_pipeline()?));
+        }
+
+        Ok(AndOr { first, rest })
+    }
+
+    /// pipeline := command ('|' NEWLINE* command)*
+    fn parse_pipeline(&mut self) -> ShellResult<Pipeline> {
+        let mut commands = vec![self.parse_command()?];
+
+        while self.peek() == Some(&Token::Pipe) {
+            self.advance();
+            self.skip_newlines();
+            commands.push(self.parse_command()?);
+        }
+
+        Ok(Pipeline { commands })
+    }
+
+    /// Whether the current token starts a compound command
+    fn at_compound_command(&self) -> bool {
+        match self.peek() {
+            Some(Token::LParen) => true,
+            Some(Token::Word(w)) => OPENING_WORDS.contains(&w.as_str()),
+            _ => false,
+        }
+    }
+
+    /// Whether the tokens at the current position are `NAME ( )`
+    fn at_function_definition(&self) -> bool {
+        matches!(
+            self.tokens.get(self.pos..self.pos + 3),
+            Some([Token::Word(_), Token::LParen, Token::RParen])
+        )
+    }
+
+    /// command := ARITHMETIC | compound_command redirect* | function_def | simple_command
+    fn parse_command(&mut self) -> ShellResult<Command> {
+        let compound = match self.peek() {
+            Some(Token::Arithmetic(expr)) => {
+                let expr = expr.clone();
+                self.advance();
+                return Ok(Command::Arithmetic(expr));
+            }
+            Some(Token::LParen) => {
+                self.advance();
+                let body = self.parse_compound_list()?;
+                if self.peek() != Some(&Token::RParen) {
+                    return Err(self.unexpected());
+                }
+                self.advance();
+                CompoundCommand::Subshell(body)
+            }
+            Some(Token::Word(w)) if w == "{" => {
+                self.advance();
+                let body = self.parse_compound_list()?;
+                self.expect_word("}")?;
+                CompoundCommand::Group(body)
+            }
+            Some(Token::Word(w)) if w == "if" => self.parse_if()?,
+            Some(Token::Word(w)) if w == "while" || w == "until" => self.parse_while()?,
+            Some(Token::Word(w)) if w == "for" => self.parse_for()?,
+            Some(Token::Word(w)) if w == "case" => self.parse_case()?,
+            Some(Token::Word(w)) if w == "function" => return self.parse_function(),
+            _ if self.at_function_definition() => return self.parse_function(),
+            _ => return Ok(Command::Simple(self.parse_simple_command()?)),
+        };
+
+        let mut redirects = Vec::new();
+        while self.at_redirect() {
+            redirects.push(self.parse_redirect()?);
+        }
+        Ok(Command::Compound(compound, redirects))
+    }
+
+    /// function_def := NAME '(' ')' NEWLINE* compound_command redirect*
+    ///               | 'function' NAME ['(' ')'] NEWLINE* compound_command redirect*
+    fn parse_function(&mut self) -> ShellResult<Command> {
+        if self.at_word("function") {
+            self.advance();
+        }
+
+        let name = match self.peek() {
+            Some(Token::Word(w)) if is_function_name(w) => w.clone(),
+            Some(Token::Word(w)) => {
+                return Err(ShellError::SyntaxError(format!(
+                    "`{}': not a valid identifier",
+                    w
+                )))
+            }
+            _ => return Err(self.unexpected()),
+        };
+        self.advance();
+
+        if self.peek() == Some(&Token::LParen) {
+            self.advance();
+            if self.peek() != Some(&Token::RParen) {
+                return Err(self.unexpected());
+            }
+            self.advance();
+        }
+
+        self.skip_newlines();
+        if !self.at_compound_command() {
+            return Err(self.unexpected());
+        }
+        match self.parse_command()? {
+            Command::Compound(body, redirects) => Ok(Command::Function(FunctionDef {
+                name,
+                body,
+                redirects,
+            })),
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    /// The body of a compound command, which must contain at least one command
+    fn parse_compound_list(&mut self) -> ShellResult<List> {
+        let list = self.parse_list()?;
+        if list.items.is_empty() {
+            return Err(self.unexpected());
+        }
+        Ok(list)
+    }
+
+    /// if := 'if' list 'then' list ('elif' list 'then' list)* ['else' list] 'fi'
+    fn parse_if(&mut self) -> ShellResult<CompoundCommand> {
+        let mut branches = Vec::new();
+        let mut else_body = None;
+
+        // The first branch starts with `if`, the others with `elif`
+        self.advance();
+        loop {
+            let condition = self.parse_compound_list()?;
+            self.expect_word("then")?;
+            branches.push((condition, self.parse_compound_list()?));
+
+            if self.at_word("elif") {
+                self.advance();
+                continue;
+            }
+            if self.at_word("else") {
+                self.advance();
+                else_body = Some(self.parse_compound_list()?);
+            }
+            self.expect_word("fi")?;
+            break;
+        }
+
+        Ok(CompoundCommand::If {
+            branches,
+            else_body,
+        })
+    }
+
+    /// while := ('while' | 'until') list do_group
+    fn parse_while(&mut self) -> ShellResult<CompoundCommand> {
+        let until = self.at_word("until");
+        self.advance();
+        let condition = self.parse_compound_list()?;
+        let body = self.parse_do_group()?;
+        Ok(CompoundCommand::While {
+            condition,
+            body,
+            until,
+        })
+    }
+
+    /// for := 'for' NAME ['in' WORD*] (';' | NEWLINE) do_group
+    ///      | 'for' ARITHMETIC [';'] do_group
+    fn parse_for(&mut self) -> ShellResult<CompoundCommand> {
+        self.advance();
+
+        let name = match self.peek() {
+            Some(Token::Arithmetic(expr)) => {
+                let parts: Vec<&str> = expr.split(';').collect();
+                let [init, condition, step] = parts.as_slice() else {
+                    return Err(self.unexpected());
+                };
+                let (init, condition, step) =
+                    (init.to_string(), condition.to_string(), step.to_string());
+                self.advance();
+                if self.peek() == Some(&Token::Semi) {
+                    self.advance();
+                }
+                let body = self.parse_do_group()?;
+                return Ok(CompoundCommand::ArithFor {
+                    init,
+                    condition,
+                    step,
+                    body,
+                });
+            }
+            Some(Token::Word(w)) if is_valid_name(w) => w.clone(),
+            Some(Token::Word(w)) => {
+                return Err(ShellError::SyntaxError(format!(
+                    "`{}': not a valid identifier",
+                    w
+                )))
+            }
+            _ => return Err(self.unexpected()),
+        };
+        self.advance();
+
+        self.skip_newlines();
+        let mut words = None;
+        if self.at_word("in") {
+            self.advance();
+            let mut list = Vec::new();
+            while let Some(Token::Word(w)) = self.peek() {
+                list.push(Word(w.clone()));
+                self.advance();
+            }
+            match self.peek() {
+                Some(Token::Semi) | Some(Token::Newline) => {
+                    self.advance();
+                }
+                _ => return Err(self.unexpected()),
+            }
+            words = Some(list);
+        } else if self.peek() == Some(&Token::Semi) {
+            self.advance();
+        }
+
+        let body = self.parse_do_group()?;
+        Ok(CompoundCommand::For { name, words, body })
+    }
+
+    /// do_group := NEWLINE* 'do' list 'done'
+    fn parse_do_group(&mut self) -> ShellResult<List> {
+        self.skip_newlines();
+        self.expect_word("do")?;
+        let body = self.parse_compound_list()?;
+        self.expect_word("done")?;
+        Ok(body)
+    }
+
+    /// case := 'case' WORD NEWLINE* 'in' case_item* 'esac'
+    /// case_item := ['('] WORD ('|' WORD)* ')' list [';;' | ';&' | ';;&']
+    fn parse_case(&mut self) -> ShellResult<CompoundCommand> {
+        self.advance();
+        let word = self.expect_case_word()?;
+        self.skip_newlines();
+        self.expect_word("in")?;
+
+        let mut items = Vec::new();
+        loop {
+            self.skip_newlines();
+            if self.at_word("esac") {
+                self.advance();
+                break;
+            }
+
+            if self.peek() == Some(&Token::LParen) {
+                self.advance();
+            }
+            let mut patterns = vec![self.expect_case_word()?];
+            while self.peek() == Some(&Token::Pipe) {
+                self.advance();
+                patterns.push(self.expect_case_word()?);
+            }
+            if self.peek() != Some(&Token::RParen) {
+                return Err(self.unexpected());
+            }
+            self.advance();
+
+            let body = self.parse_list()?;
+            let terminator = match self.peek() {
+                Some(Token::DSemi) => CaseTerminator::Break,
+                Some(Token::SemiAnd) => CaseTerminator::FallThrough,
+                Some(Token::DSemiAnd) => CaseTerminator::Continue,
+                // The last item doesn't need a terminator
+                _ => {
+                    self.expect_word("esac")?;
+                    items.push(CaseItem {
+                        patterns,
+                        body,
+                        terminator: CaseTerminator::Break,
+                    });
+                    break;
+                }
+            };
+            self.advance();
+            items.push(CaseItem {
+                patterns,
+                body,
+                terminator,
+            });
+        }
+
+        Ok(CompoundCommand::Case { word, items })
+    }
+
+    /// Consume the word being matched by `case` or one of its patterns
+    fn expect_case_word(&mut self) -> ShellResult<Word> {
+        match self.peek() {
+            Some(Token::Word(w)) => {
+                let word = Word(w.clone());
+                self.advance();
+                Ok(word)
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    /// simple_command := (ASSIGNMENT | redirect)* (WORD | redirect)*
+    fn parse_simple_command(&mut self) -> ShellResult<SimpleCommand> {
+        let mut command = SimpleCommand::default();
+
+        loop {
+            match self.peek() {
+                Some(Token::Word(_)) => {
+                    if let Some(Token::Word(w)) = self.advance() {
+                        // Assignments are only recognised before the command name
+                        match split_assignment(&w) {
+                            Some(assignment) if command.words.is_empty() => {
+                                command.assignments.push(assignment)
+                            }
+                            _ => command.words.push(Word(w)),
+                        }
+                    }
+                }
+                _ if self.at_redirect() => {
+                    let redirect = self.parse_redirect()?;
+                    command.redirects.push(redirect);
+                }
+                _ => break,
+            }
+        }
+
+        if command.assignments.is_empty()
+            && command.words.is_empty()
+            && command.redirects.is_empty()
+        {
+            return Err(self.unexpected());
+        }
+
+        Ok(command)
+    }
+
+    /// redirect := [IO_NUMBER] (REDIRECT_OP WORD | HEREDOC)
+    fn parse_redirect(&mut self) -> ShellResult<Redirect> {
+        let fd = match self.peek() {
+            Some(Token::IoNumber(n)) => {
+                let n = *n;
+                self.advance();
+                Some(n)
+            }
+            _ => None,
+        };
+
+        let op = match self.peek() {
+            Some(Token::Great) => RedirectOp::Output,
+            Some(Token::DGreat) => RedirectOp::Append,
+            Some(Token::Clobber) => RedirectOp::Clobber,
+            Some(Token::Less) => RedirectOp::Input,
+            Some(Token::TLess) => RedirectOp::HereString,
+            Some(Token::GreatAnd) => RedirectOp::DupOutput,
+            Some(Token::LessAnd) => RedirectOp::DupInput,
+            Some(Token::LessGreat) => RedirectOp::ReadWrite,
+            Some(Token::AndGreat) => RedirectOp::OutputAll,
+            Some(Token::AndDGreat) => RedirectOp::AppendAll,
+            Some(Token::HereDoc { body, expand }) => {
+                let redirect = Redirect {
+                    fd,
+                    op: RedirectOp::HereDoc { expand: *expand },
+                    target: Word(body.clone()),
+                };
+                self.advance();
+                return Ok(redirect);
+            }
+            _ => return Err(self.unexpected()),
+        };
+        self.advance();
+
+        match self.peek() {
+            Some(Token::Word(w)) => {
+                let target = Word(w.clone());
+                self.advance();
+                Ok(Redirect { fd, op, target })
+            }
+            // The target must be on the same line
+            None => Err(ShellError::SyntaxError(
+                "syntax error near unexpected token `newline'".to_string(),
+            )),
+            _ => Err(self.unexpected()),
+        }
+    }
+}
+
+/// Check whether a word can name a function: it must not be a number or
+/// contain quoting, expansions or `=`
+fn is_function_name(word: &str) -> bool {
+    !word.is_empty()
+        && !word.chars().all(|c| c.is_ascii_digit())
+        && !word.contains(['\\', '\'', '"', '$', '`', '='])
+}
+
+/// Split a `NAME=value` word into an assignment, if it is one
+fn split_assignment(word: &str) -> Option<Assignment> {
+    let (name, value) = word.split_once('=')?;
+    if !is_valid_name(name) {
+        return None;
+    }
+    Some(Assignment {
+        name: name.to_string(),
+        value: Word(value.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The only simple command of a one-command line
+    fn simple(input: &str) -> SimpleCommand {
+        let list = parse(input).unwrap();
+        match list.items.as_slice() {
+            [AndOr { first, rest }] if rest.is_empty() => match first.commands.as_slice() {
+                [Command::Simple(command)] => command.clone(),
+                other => panic!("expected a simple command, got {:?}", other),
+            },
+            other => panic!("expected one command, got {:?}", other),
+        }
+    }
+
+    fn words(command: &SimpleCommand) -> Vec<&str> {
+        command.words.iter().map(|w| w.as_str()).collect()
+    }
+
+    #[test]
+    fn pipelines() {
+        let list = parse("echo 'a|b' | tr a-z A-Z | cat \"x|y\"").unwrap();
+        let commands = &list.items[0].first.commands;
+        assert_eq!(commands.len(), 3);
+        let Command::Simple(last) = &commands[2] else {
+            panic!("expected a simple command");
+        };
+        assert_eq!(words(last), ["cat", "\"x|y\""]);
+
+        assert_eq!(words(&simple("echo 'a|b'")), ["echo", "'a|b'"]);
+    }
+
+    #[test]
+    fn redirections() {
+        let command = simple("cmd arg 2>&1 3>f >>log <in");
+        assert_eq!(words(&command), ["cmd", "arg"]);
+        let redirects: Vec<(Option<u32>, RedirectOp, &str)> = command
+            .redirects
+            .iter()
+            .map(|r| (r.fd, r.op, r.target.as_str()))
+            .collect();
+        assert_eq!(
+            redirects,
+            [
+                (Some(2), RedirectOp::DupOutput, "1"),
+                (Some(3), RedirectOp::Output, "f"),
+                (None, RedirectOp::Append, "log"),
+                (None, RedirectOp::Input, "in"),
+            ]
+        );
+
+        // Redirections may come before and between the words
+        let command = simple(">out echo 2>err hi");
+        assert_eq!(words(&command), ["echo", "hi"]);
+        assert_eq!(command.redirects.len(), 2);
+    }
+
+    #[test]
+    fn syntax_errors() {
+        for input in ["| a", "a | | b", "a > > b", "echo >", ")"] {
+            assert!(
+                matches!(parse(input), Err(ShellError::SyntaxError(_))),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn heredoc_redirects() {
+        let command = simple("cat <<EOF >out\nhello\nEOF");
+        assert_eq!(words(&command), ["cat"]);
+        assert_eq!(
+            command.redirects[0].op,
+            RedirectOp::HereDoc { expand: true }
+        );
+        assert_eq!(command.redirects[0].target.as_str(), "hello\n");
+        assert_eq!(command.redirects[1].op, RedirectOp::Output);
+
+        let command = simple("cat <<< \"$x\"");
+        assert_eq!(command.redirects[0].op, RedirectOp::HereString);
+    }
+
+    #[test]
+    fn incomplete_input_or_syntax_error() {
+        // More lines can finish these, so the prompt asks for them
+        for input in [
+            "echo 'abc",
+            "echo a |",
+            "true &&",
+            "false ||\n",
+            "cat <<EOF\nbody",
+            "echo a\\",
+        ] {
+            assert!(is_incomplete(input), "{:?}", input);
+        }
+        // No further line can fix these
+        for input in [
+            "a | | b",
+            "cat <<",
+            "cat << | x",
+            "echo >",
+            "a && && b",
+            ";",
+        ] {
+            assert!(!is_incomplete(input), "{:?}", input);
+            assert!(
+                matches!(parse(input), Err(ShellError::SyntaxError(_))),
+                "{:?}",
+                input
+            );
+        }
+        assert!(!is_incomplete("echo done"));
+    }
+
+    /// The only compound command of a one-command line
+    fn compound(input: &str) -> CompoundCommand {
+        let list = parse(input).unwrap();
+        match list.items[0].first.commands.as_slice() {
+            [Command::Compound(command, _)] => command.clone(),
+            other => panic!("expected a compound command, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reserved_words() {
+        let CompoundCommand::If {
+            branches,
+            else_body,
+        } = compound("if a; then b; elif c\nthen d; else e; fi")
+        else {
+            panic!("expected an if command");
+        };
+        assert_eq!(branches.len(), 2);
+        assert!(else_body.is_some());
+
+        assert!(matches!(
+            compound("while a; do b; done"),
+            CompoundCommand::While { until: false, .. }
+        ));
+        assert!(matches!(
+            compound("until a\ndo b\ndone"),
+            CompoundCommand::While { until: true, .. }
+        ));
+        let CompoundCommand::For {
+            name,
+            words: loop_words,
+            ..
+        } = compound("for x in a b; do echo $x; done")
+        else {
+            panic!("expected a for loop");
+        };
+        assert_eq!(name, "x");
+        assert_eq!(loop_words.map(|w| w.len()), Some(2));
+        let CompoundCommand::Case { items, .. } = compound("case $x in a|b) one;; *) two;; esac")
+        else {
+            panic!("expected a case command");
+        };
+        assert_eq!(items[0].patterns.len(), 2);
+
+        // Reserved words are only recognised in command position
+        assert_eq!(
+            words(&simple("echo if then fi done esac")),
+            ["echo", "if", "then", "fi", "done", "esac"]
+        );
+        assert_eq!(words(&simple("'if' x")), ["'if'", "x"]);
+        assert!(matches!(
+            compound("if echo then; then :; fi"),
+            CompoundCommand::If { .. }
+        ));
+    }
+
+    #[test]
+    fn unfinished_compound_commands() {
+        for input in [
+            "if true",
+            "if true; then",
+            "while true; do echo",
+            "for x in a b",
+            "case x in",
+        ] {
+            assert!(is_incomplete(input), "{:?}", input);
+        }
+        for input in [
+            "then echo",
+            "fi",
+            "if true; fi",
+            "for 1x in a; do :; done",
+            "done",
+        ] {
+            assert!(
+                matches!(parse(input), Err(ShellError::SyntaxError(_))),
+                "{:?}",
+                input
+            );
+        }
+    }
+}
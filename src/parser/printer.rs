@@ -0,0 +1,239 @@
+use super::ast::{
+    AndOr, AndOrOp, CaseTerminator, Command, CompoundCommand, FunctionDef, List, Pipeline,
+    Redirect, RedirectOp,
+};
+
+/// Format a function definition back into shell source, laid out like bash does
+pub fn function_source(def: &FunctionDef) -> String {
+    let mut printer = Printer::default();
+    printer.function(def);
+    printer.flush_heredocs();
+    printer.out
+}
+
+/// Builds indented source text from the AST
+#[derive(Default)]
+struct Printer {
+    out: String,
+    indent: usize,
+    /// Here-document bodies to write after the current line
+    heredocs: Vec<String>,
+}
+
+impl Printer {
+    /// Start a new line at the current indentation
+    fn newline(&mut self) {
+        self.flush_heredocs();
+        self.out.push('\n');
+        self.out.push_str(&"    ".repeat(self.indent));
+    }
+
+    fn flush_heredocs(&mut self) {
+        for body in std::mem::take(&mut self.heredocs) {
+            self.out.push('\n');
+            self.out.push_str(&body);
+            self.out.push_str("EOF");
+        }
+    }
+
+    fn function(&mut self, def: &FunctionDef) {
+        self.out.push_str(&def.name);
+        self.out.push_str(" () ");
+        self.newline();
+        self.compound(&def.body);
+        self.redirects(&def.redirects);
+    }
+
+    /// A list with one command per line
+    fn list(&mut self, list: &List) {
+        for (i, and_or) in list.items.iter().enumerate() {
+            if i > 0 {
+                self.out.push(';');
+                self.newline();
+            }
+            self.and_or(and_or);
+        }
+    }
+
+    /// A list on a single line, e.g. a loop condition
+    fn inline_list(&mut self, list: &List) {
+        for (i, and_or) in list.items.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str("; ");
+            }
+            self.and_or(and_or);
+        }
+    }
+
+    /// A list indented one level on its own lines
+    fn block(&mut self, list: &List) {
+        self.indent += 1;
+        self.newline();
+        self.list(list);
+        self.indent -= 1;
+        self.newline();
+    }
+
+    fn and_or(&mut self, and_or: &AndOr) {
+        self.pipeline(&and_or.first);
+        for (op, pipeline) in &and_or.rest {
+            self.out.push_str(match op {
+                AndOrOp::And => " && ",
+                AndOrOp::Or => " || ",
+            });
+            self.pipeline(pipeline);
+        }
+    }
+
+    fn pipeline(&mut self, pipeline: &Pipeline) {
+        for (i, command) in pipeline.commands.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(" | ");
+            }
+            self.command(command);
+        }
+    }
+
+    fn command(&mut self, command: &Command) {
+        match command {
+            Command::Simple(simple) => {
+                let mut parts: Vec<String> = simple
+                    .assignments
+                    .iter()
+                    .map(|a| format!("{}={}", a.name, a.value.as_str()))
+                    .collect();
+                parts.extend(simple.words.iter().map(|w| w.as_str().to_string()));
+                for redirect in &simple.redirects {
+                    let text = self.redirect(redirect);
+                    parts.push(text);
+                }
+                self.out.push_str(&parts.join(" "));
+            }
+            Command::Arithmetic(expr) => {
+                self.out.push_str(&format!("(({}))", expr));
+            }
+            Command::Compound(compound, redirects) => {
+                self.compound(compound);
+                self.redirects(redirects);
+            }
+            Command::Function(def) => self.function(def),
+        }
+    }
+
+    fn compound(&mut self, compound: &CompoundCommand) {
+        match compound {
+            CompoundCommand::Subshell(body) => {
+                self.out.push_str("( ");
+                self.block(body);
+                self.out.push(')');
+            }
+            CompoundCommand::Group(body) => {
+                self.out.push_str("{ ");
+                self.block(body);
+                self.out.push('}');
+            }
+            CompoundCommand::If {
+                branches,
+                else_body,
+            } => {
+                for (i, (condition, body)) in branches.iter().enumerate() {
+                    self.out.push_str(if i == 0 { "if " } else { "elif " });
+                    self.inline_list(condition);
+                    self.out.push_str("; then");
+                    self.block(body);
+                }
+                if let Some(body) = else_body {
+                    self.out.push_str("else");
+                    self.block(body);
+                }
+                self.out.push_str("fi");
+            }
+            CompoundCommand::While {
+                condition,
+                body,
+                until,
+            } => {
+                self.out.push_str(if *until { "until " } else { "while " });
+                self.inline_list(condition);
+                self.out.push_str("; do");
+                self.block(body);
+                self.out.push_str("done");
+            }
+            CompoundCommand::For { name, words, body } => {
+                self.out.push_str("for ");
+                self.out.push_str(name);
+                if let Some(words) = words {
+                    self.out.push_str(" in");
+                    for word in words {
+                        self.out.push(' ');
+                        self.out.push_str(word.as_str());
+                    }
+                }
+                self.out.push_str("; do");
+                self.block(body);
+                self.out.push_str("done");
+            }
+            CompoundCommand::ArithFor {
+                init,
+                condition,
+                step,
+                body,
+            } => {
+                self.out
+                    .push_str(&format!("for (({};{};{})); do", init, condition, step));
+                self.block(body);
+                self.out.push_str("done");
+            }
+            CompoundCommand::Case { word, items } => {
+                self.out.push_str(&format!("case {} in ", word.as_str()));
+                self.indent += 1;
+                for item in items {
+                    self.newline();
+                    let patterns: Vec<&str> = item.patterns.iter().map(|p| p.as_str()).collect();
+                    self.out.push_str(&patterns.join(" | "));
+                    self.out.push(')');
+                    self.block(&item.body);
+                    self.out.push_str(match item.terminator {
+                        CaseTerminator::Break => ";;",
+                        CaseTerminator::FallThrough => ";&",
+                        CaseTerminator::Continue => ";;&",
+                    });
+                }
+                self.indent -= 1;
+                self.newline();
+                self.out.push_str("esac");
+            }
+        }
+    }
+
+    fn redirects(&mut self, redirects: &[Redirect]) {
+        for redirect in redirects {
+            let text = self.redirect(redirect);
+            self.out.push(' ');
+            self.out.push_str(&text);
+        }
+    }
+
+    fn redirect(&mut self, redirect: &Redirect) -> String {
+        let fd = redirect.fd.map(|fd| fd.to_string()).unwrap_or_default();
+        let op = match redirect.op {
+            RedirectOp::Output => ">",
+            RedirectOp::Append => ">>",
+            RedirectOp::Clobber => ">|",
+            RedirectOp::Input => "<",
+            RedirectOp::ReadWrite => "<>",
+            RedirectOp::DupOutput => ">&",
+            RedirectOp::DupInput => "<&",
+            RedirectOp::OutputAll => "&>",
+            RedirectOp::AppendAll => "&>>",
+            RedirectOp::HereString => "<<< ",
+            RedirectOp::HereDoc { expand } => {
+                // The body follows the line under a fixed delimiter
+                self.heredocs.push(redirect.target.as_str().to_string());
+                let op = if expand { "<<EOF" } else { "<<'EOF'" };
+                return format!("{}{}", fd, op);
+            }
+        };
+        format!("{}{}{}", fd, op, redirect.target.as_str())
+    }
+}
@@ -1,165 +1,161 @@
-use crate::errors::{ShellError, ShellResult};
-use crate::redirection::parse_redirection;
-use crate::commands::BUILTINS;
-use std::process::{Command, Stdio, Child};
-use std::env;
-use std::path::Path;
-
-/// Check if a command is a builtin
-fn is_builtin(name: &str) -> bool {
-    BUILTINS.is_builtin(name)
+use crate::commands::external::{exit_code, find_command_path, setup_environment, spawn_error};
+use crate::commands::{handle_command, BUILTINS};
+use crate::errors::{report_error, ShellResult};
+use crate::executor::{apply_prefix_assignments, execute_command};
+use crate::expansion::{expand_words, process_substitution_mark, reap_process_substitutions};
+use crate::functions;
+use crate::parser::ast::Command as AstCommand;
+use crate::process::{create_pipe, fork_shell, wait_for_child};
+use crate::redirection::{
+    resolve_redirections, setup_builtin_redirection, setup_redirection, Redirections,
+};
+use std::fs::File;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, Stdio};
+
+/// A started pipeline stage
+enum Stage {
+    /// An external command
+    Process(Child),
+    /// A forked copy of the shell running a builtin or compound command
+    Forked(libc::pid_t),
+    /// A stage that already finished with this status
+    Done(i32),
 }
 
-/// Split input into pipeline stages
-pub fn split_pipeline(input: &str) -> Vec<&str> {
-    input.split('|').map(|s| s.trim()).collect()
-}
-
-/// Check if input contains a pipeline
-pub fn is_pipeline(input: &str) -> bool {
-    input.contains('|')
+impl Stage {
+    fn wait(self) -> i32 {
+        match self {
+            Stage::Process(mut child) => child.wait().map(exit_code).unwrap_or(1),
+            Stage::Forked(pid) => wait_for_child(pid),
+            Stage::Done(status) => status,
+        }
+    }
 }
 
 /// Execute a pipeline of commands
-pub fn execute_pipeline(stages: Vec<Vec<String>>) -> ShellResult<()> {
-    if stages.is_empty() {
-        return Ok(());
-    }
+/// Returns the exit status of the last stage
+pub fn execute_pipeline(stages: &[AstCommand]) -> ShellResult<i32> {
+    let substitutions = process_substitution_mark();
+    let mut previous_stdout: Option<File> = None;
+    let mut started: Vec<Stage> = Vec::new();
 
-    if stages.len() == 1 {
-        // Single command, no pipeline needed
-        return crate::commands::run_external_command(&stages[0]);
+    for (i, stage) in stages.iter().enumerate() {
+        let is_last = i == stages.len() - 1;
+        let stdin = previous_stdout.take();
+        let stdout = if is_last {
+            None
+        } else {
+            let (read_end, write_end) = create_pipe()?;
+            previous_stdout = Some(read_end);
+            Some(write_end)
+        };
+
+        // A failing stage is reported but the rest of the pipeline still runs
+        match start_stage(stage, stdin, stdout) {
+            Ok(stage) => started.push(stage),
+            Err(e) => {
+                report_error(&e);
+                started.push(Stage::Done(e.exit_status()));
+            }
+        }
     }
 
-    let mut previous_stdout: Option<std::process::ChildStdout> = None;
-    let mut children: Vec<Child> = Vec::new();
-
-    for (i, args) in stages.iter().enumerate() {
-        if args.is_empty() {
-            continue;
-        }
+    // Wait for every stage; the last one sets the status
+    let mut last_status = 0;
+    for stage in started {
+        last_status = stage.wait();
+    }
+    reap_process_substitutions(substitutions);
 
-        let is_last = i == stages.len() - 1;
-        let command_name = &args[0];
+    Ok(last_status)
+}
 
-        // Parse any redirections (only meaningful for last command's stdout/stderr)
-        let (filtered_args, stdout_redir, stderr_redir) = parse_redirection(args)?;
+/// Start one stage with the given pipe ends (None means the shell's own stdin/stdout)
+fn start_stage(
+    stage: &AstCommand,
+    stdin: Option<File>,
+    stdout: Option<File>,
+) -> ShellResult<Stage> {
+    let simple = match stage {
+        AstCommand::Simple(simple) => simple,
+        _ => return fork_stage(stdin, stdout, || execute_command(stage)),
+    };
+
+    let args = expand_words(&simple.words)?;
+    let redirections = resolve_redirections(&simple.redirects)?;
+
+    // `NAME=value` prefixes apply to this stage only
+    let _assignments = apply_prefix_assignments(&simple.assignments)?;
+
+    if args.is_empty() {
+        // Redirections without a command still create/truncate their files
+        let _guard = setup_builtin_redirection(&redirections)?;
+        return Ok(Stage::Done(0));
+    }
 
-        // Check if this is a builtin command AND it's the last stage
-        // (only handle builtins specially at the end; in the middle, use external versions for piping)
-        if is_last && is_builtin(command_name) {
-            // Wait for all previous children first
-            for mut child in children.drain(..) {
-                let _ = child.wait();
-            }
+    if !BUILTINS.is_builtin(&args[0]) && !functions::is_function(&args[0]) {
+        return spawn_external(&args, &redirections, stdin, stdout).map(Stage::Process);
+    }
 
-            // Drain previous stdout (builtins like type/pwd don't read stdin)
-            if let Some(mut prev_stdout) = previous_stdout.take() {
-                use std::io::Read;
-                let mut buf = Vec::new();
-                let _ = prev_stdout.read_to_end(&mut buf);
-            }
+    // Like bash, builtins and functions in a pipeline run in a forked shell, so they can
+    // read from and write to the pipes (and `exit` only ends that stage)
+    fork_stage(stdin, stdout, || handle_command(&args, &redirections))
+}
 
-            // Check for exit command
-            if let Some(code) = BUILTINS.check_exit(command_name, &filtered_args) {
-                std::process::exit(code);
+/// Run a stage in a forked shell with its stdin/stdout connected to the pipes
+fn fork_stage<F: FnOnce() -> ShellResult<i32>>(
+    stdin: Option<File>,
+    stdout: Option<File>,
+    body: F,
+) -> ShellResult<Stage> {
+    let pid = fork_shell(move || {
+        unsafe {
+            if let Some(stdin) = &stdin {
+                libc::dup2(stdin.as_raw_fd(), libc::STDIN_FILENO);
             }
-
-            // Execute builtin using registry
-            if let Some(result) = BUILTINS.execute(command_name, &filtered_args) {
-                result?;
+            if let Some(stdout) = &stdout {
+                libc::dup2(stdout.as_raw_fd(), libc::STDOUT_FILENO);
             }
-
-            // Builtins don't produce piped output, so clear previous_stdout
-            previous_stdout = None;
-            continue;
         }
 
-        // Find the command path
-        let cmd_path = find_command_path(command_name)?;
-
-        let mut cmd = Command::new(&cmd_path);
-        cmd.args(&filtered_args[1..]);
-
-        // Set up stdin from previous command's stdout
-        if let Some(prev_stdout) = previous_stdout.take() {
-            cmd.stdin(Stdio::from(prev_stdout));
-        }
-
-        // Set up stdout
-        if is_last {
-            // Last command: output to stdout or redirect to file
-            if let Some(redir) = &stdout_redir {
-                let file = std::fs::OpenOptions::new()
-                    .write(true)
-                    .create(true)
-                    .truncate(matches!(redir.mode, crate::redirection::RedirectionMode::Overwrite))
-                    .append(matches!(redir.mode, crate::redirection::RedirectionMode::Append))
-                    .open(&redir.file)
-                    .map_err(|e| ShellError::RedirectionError(format!("Failed to open '{}': {}", redir.file, e)))?;
-                cmd.stdout(Stdio::from(file));
-            } else {
-                cmd.stdout(Stdio::inherit());
+        match body() {
+            Ok(status) => status,
+            Err(e) => {
+                report_error(&e);
+                e.exit_status()
             }
-        } else {
-            // Not last: pipe stdout to next command
-            cmd.stdout(Stdio::piped());
-        }
-
-        // Set up stderr (redirect if specified, otherwise inherit)
-        if let Some(redir) = &stderr_redir {
-            let file = std::fs::OpenOptions::new()
-                .write(true)
-                .create(true)
-                .truncate(matches!(redir.mode, crate::redirection::RedirectionMode::Overwrite))
-                .append(matches!(redir.mode, crate::redirection::RedirectionMode::Append))
-                .open(&redir.file)
-                .map_err(|e| ShellError::RedirectionError(format!("Failed to open '{}': {}", redir.file, e)))?;
-            cmd.stderr(Stdio::from(file));
-        } else {
-            cmd.stderr(Stdio::inherit());
-        }
-
-        let mut child = cmd.spawn().map_err(|e| {
-            ShellError::ExecutionError(format!("Failed to execute {}: {}", command_name, e))
-        })?;
-
-        // Capture stdout for next command in pipeline
-        if !is_last {
-            previous_stdout = child.stdout.take();
         }
+    })?;
 
-        children.push(child);
-    }
-
-    // Wait for all children to complete
-    for mut child in children {
-        let _ = child.wait();
-    }
-
-    Ok(())
+    Ok(Stage::Forked(pid))
 }
 
-/// Find command path (check if it's a path or search in PATH)
-fn find_command_path(command_name: &str) -> ShellResult<String> {
-    // If command contains '/', use it directly
-    if command_name.contains('/') {
-        if Path::new(command_name).exists() {
-            return Ok(command_name.to_string());
-        }
-        return Err(ShellError::CommandNotFound(format!("{}: command not found", command_name)));
+/// Start an external command with its stdin/stdout connected to the pipes
+fn spawn_external(
+    args: &[String],
+    redirections: &Redirections,
+    stdin: Option<File>,
+    stdout: Option<File>,
+) -> ShellResult<Child> {
+    let command_name = &args[0];
+    let cmd_path = find_command_path(command_name)?;
+
+    let mut cmd = Command::new(&cmd_path);
+    cmd.arg0(command_name);
+    cmd.args(&args[1..]);
+    setup_environment(&mut cmd);
+
+    if let Some(stdin) = stdin {
+        cmd.stdin(Stdio::from(stdin));
     }
-
-    // Try to find in PATH
-    if let Ok(path_var) = env::var("PATH") {
-        for path_dir in path_var.split(':') {
-            let full_path = format!("{}/{}", path_dir, command_name);
-            if Path::new(&full_path).exists() {
-                return Ok(full_path);
-            }
-        }
+    if let Some(stdout) = stdout {
+        cmd.stdout(Stdio::from(stdout));
     }
 
-    // Try as-is (might work for commands in current dir or system knows about)
-    Ok(command_name.to_string())
-}
\ No newline at end of file
+    // Explicit redirections replace the pipe ends
+    setup_redirection(&mut cmd, redirections)?;
+
+    cmd.spawn().map_err(|e| spawn_error(command_name, e))
+}
@@ -0,0 +1,64 @@
+use crate::errors::{ShellError, ShellResult};
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::unix::io::FromRawFd;
+
+/// Fork a copy of the shell. The child runs `body` and exits with the
+/// status it returns; the parent gets the child's pid
+pub fn fork_shell<F: FnOnce() -> i32>(body: F) -> ShellResult<libc::pid_t> {
+    // Anything still buffered would otherwise be written twice
+    let _ = io::stdout().flush();
+    let _ = io::stderr().flush();
+
+    let pid = unsafe { libc::fork() };
+    if pid < 0 {
+        let err = io::Error::last_os_error();
+        return Err(ShellError::ExecutionError(format!("fork failed: {}", err)));
+    }
+
+    if pid == 0 {
+        // Like other processes, a forked shell dies quietly when a pipe
+        // reader goes away, instead of failing on every write
+        unsafe {
+            libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+        }
+
+        let code = body();
+        let _ = io::stdout().flush();
+        let _ = io::stderr().flush();
+        unsafe { libc::_exit(code) }
+    }
+
+    Ok(pid)
+}
+
+/// Wait for a forked child and convert its wait status to a shell status
+pub fn wait_for_child(pid: libc::pid_t) -> i32 {
+    let mut wait_status = 0;
+    loop {
+        let result = unsafe { libc::waitpid(pid, &mut wait_status, 0) };
+        if result >= 0 {
+            break;
+        }
+        if io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+            return 1;
+        }
+    }
+
+    if libc::WIFEXITED(wait_status) {
+        libc::WEXITSTATUS(wait_status)
+    } else if libc::WIFSIGNALED(wait_status) {
+        128 + libc::WTERMSIG(wait_status)
+    } else {
+        1
+    }
+}
+
+/// Create a pipe whose ends are closed on exec. Returns (read end, write end)
+pub fn create_pipe() -> ShellResult<(File, File)> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        return Err(ShellError::IoError(io::Error::last_os_error()));
+    }
+    Ok(unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) })
+}
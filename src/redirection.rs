@@ -1,132 +1,331 @@
 use crate::errors::{ShellError, ShellResult};
-use std::fs::{File, OpenOptions};
-use std::io;
-use std::os::unix::io::{AsRawFd, FromRawFd};
-use std::process::{Command, Stdio};
+use crate::expansion::{expand_heredoc, expand_word, inherit_process_substitutions};
+use crate::options;
+use crate::parser::ast::{Redirect, RedirectOp};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Seek, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-/// Redirection mode (overwrite or append)
-#[derive(Debug, Clone, Copy)]
+/// Lowest fd used for the shell's own copies of redirected descriptors
+const SAVED_FD_BASE: RawFd = 10;
+
+/// How a redirection opens its file
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum RedirectionMode {
+    /// `<`
+    Read,
+    /// `>`, refused for existing files when noclobber is set
     Overwrite,
+    /// `>|`
+    Clobber,
+    /// `>>`
     Append,
+    /// `<>`
+    ReadWrite,
 }
 
-/// Represents a redirection specification
+/// What a redirection does to its file descriptor
+#[derive(Debug, Clone)]
+pub enum RedirectionAction {
+    /// Open a file
+    Open { file: String, mode: RedirectionMode },
+    /// Read from here-string or here-document text
+    Text(String),
+    /// Make the fd a copy of another one (`n>&m`, `n<&m`)
+    Duplicate(RawFd),
+    /// `n>&-`
+    Close,
+}
+
+/// A single redirection applied to one file descriptor
 #[derive(Debug, Clone)]
 pub struct Redirection {
-    pub file: String,
-    pub mode: RedirectionMode,
-}
-
-/// Parse redirection operators from command arguments
-/// Returns (filtered_args, stdout_redir, stderr_redir)
-pub fn parse_redirection(
-    args: &[String],
-) -> ShellResult<(Vec<String>, Option<Redirection>, Option<Redirection>)> {
-    let mut filtered_args = Vec::new();
-    let mut stdout_redir = None;
-    let mut stderr_redir = None;
-    let mut i = 0;
-
-    while i < args.len() {
-        let arg = &args[i];
-
-        if arg == ">" || arg == "1>" {
-            if i + 1 < args.len() {
-                stdout_redir = Some(Redirection {
-                    file: args[i + 1].clone(),
-                    mode: RedirectionMode::Overwrite,
-                });
-                i += 2;
-                continue;
-            } else {
-                return Err(ShellError::RedirectionError(
-                    "Expected filename after '>'".to_string(),
-                ));
+    pub fd: RawFd,
+    pub action: RedirectionAction,
+}
+
+/// The redirections of a single command, applied in order
+#[derive(Debug, Clone, Default)]
+pub struct Redirections {
+    pub ops: Vec<Redirection>,
+}
+
+impl Redirections {
+    fn push(&mut self, fd: RawFd, action: RedirectionAction) {
+        self.ops.push(Redirection { fd, action });
+    }
+
+    /// `&>file` / `&>>file`: stdout to the file and stderr to stdout
+    fn push_both(&mut self, file: String, mode: RedirectionMode) {
+        self.push(1, RedirectionAction::Open { file, mode });
+        self.push(2, RedirectionAction::Duplicate(1));
+    }
+}
+
+/// Resolve parsed redirections into the ordered fd operations to perform
+pub fn resolve_redirections(redirects: &[Redirect]) -> ShellResult<Redirections> {
+    let mut redirections = Redirections::default();
+
+    for redirect in redirects {
+        let default_fd = if redirect.op.is_input() { 0 } else { 1 };
+        let fd = redirect.fd.map_or(default_fd, |fd| fd as RawFd);
+
+        let open = |mode| -> ShellResult<RedirectionAction> {
+            Ok(RedirectionAction::Open {
+                file: expand_word(&redirect.target)?,
+                mode,
+            })
+        };
+
+        match redirect.op {
+            RedirectOp::Output => redirections.push(fd, open(RedirectionMode::Overwrite)?),
+            RedirectOp::Append => redirections.push(fd, open(RedirectionMode::Append)?),
+            RedirectOp::Clobber => redirections.push(fd, open(RedirectionMode::Clobber)?),
+            RedirectOp::Input => redirections.push(fd, open(RedirectionMode::Read)?),
+            RedirectOp::ReadWrite => redirections.push(fd, open(RedirectionMode::ReadWrite)?),
+            RedirectOp::HereString => {
+                let text = expand_word(&redirect.target)? + "\n";
+                redirections.push(fd, RedirectionAction::Text(text));
+            }
+            RedirectOp::HereDoc { expand } => {
+                let body = redirect.target.as_str();
+                let text = if expand {
+                    expand_heredoc(body)?
+                } else {
+                    body.to_string()
+                };
+                redirections.push(fd, RedirectionAction::Text(text));
+            }
+            RedirectOp::DupOutput | RedirectOp::DupInput => {
+                let target = expand_word(&redirect.target)?;
+                if target == "-" {
+                    redirections.push(fd, RedirectionAction::Close);
+                } else if let Ok(source) = target.parse::<RawFd>() {
+                    redirections.push(fd, RedirectionAction::Duplicate(source));
+                } else if redirect.op == RedirectOp::DupOutput && redirect.fd.is_none() {
+                    // `>&file` is the same as `&>file`
+                    redirections.push_both(target, RedirectionMode::Overwrite);
+                } else {
+                    return Err(ShellError::RedirectionError(format!(
+                        "{}: ambiguous redirect",
+                        target
+                    )));
+                }
             }
-        } else if arg == ">>" || arg == "1>>" {
-            if i + 1 < args.len() {
-                stdout_redir = Some(Redirection {
-                    file: args[i + 1].clone(),
-                    mode: RedirectionMode::Append,
-                });
-                i += 2;
-                continue;
-            } else {
-                return Err(ShellError::RedirectionError(
-                    "Expected filename after '>>'".to_string(),
-                ));
+            RedirectOp::OutputAll => {
+                redirections.push_both(expand_word(&redirect.target)?, RedirectionMode::Overwrite)
             }
-        } else if arg == "2>" {
-            if i + 1 < args.len() {
-                stderr_redir = Some(Redirection {
-                    file: args[i + 1].clone(),
-                    mode: RedirectionMode::Overwrite,
-                });
-                i += 2;
-                continue;
-            } else {
-                return Err(ShellError::RedirectionError(
-                    "Expected filename after '2>'".to_string(),
-                ));
+            RedirectOp::AppendAll => {
+                redirections.push_both(expand_word(&redirect.target)?, RedirectionMode::Append)
             }
-        } else if arg == "2>>" {
-            if i + 1 < args.len() {
-                stderr_redir = Some(Redirection {
-                    file: args[i + 1].clone(),
-                    mode: RedirectionMode::Append,
-                });
-                i += 2;
-                continue;
-            } else {
-                return Err(ShellError::RedirectionError(
-                    "Expected filename after '2>>'".to_string(),
-                ));
+        }
+    }
+
+    Ok(redirections)
+}
+
+/// Open the file or text a redirection reads from or writes to.
+/// Text is stored in an unlinked temporary file so it can be read like any other file
+fn open_action(action: &RedirectionAction) -> ShellResult<Option<File>> {
+    let (file, mode) = match action {
+        RedirectionAction::Open { file, mode } => (file, *mode),
+        RedirectionAction::Text(text) => {
+            return text_file(text).map(Some).map_err(ShellError::IoError)
+        }
+        _ => return Ok(None),
+    };
+
+    if mode == RedirectionMode::Overwrite && options::is_enabled("noclobber") {
+        return open_noclobber(file).map(Some);
+    }
+
+    let mut options = OpenOptions::new();
+    match mode {
+        RedirectionMode::Read => options.read(true),
+        RedirectionMode::Overwrite | RedirectionMode::Clobber => {
+            options.write(true).create(true).truncate(true)
+        }
+        RedirectionMode::Append => options.append(true).create(true),
+        RedirectionMode::ReadWrite => options.read(true).write(true).create(true),
+    };
+
+    options.open(file).map(Some).map_err(|e| match mode {
+        RedirectionMode::Read => {
+            ShellError::RedirectionError(format!("{}: {}", file, io_message(&e)))
+        }
+        _ => ShellError::RedirectionError(format!("Failed to open '{}': {}", file, e)),
+    })
+}
+
+/// Open a `>` target with noclobber set: new files are created, but existing
+/// regular files are never truncated. Other files (like `/dev/null`) are
+/// opened for writing as usual
+fn open_noclobber(file: &str) -> ShellResult<File> {
+    let open_error =
+        |e: io::Error| ShellError::RedirectionError(format!("Failed to open '{}': {}", file, e));
+
+    match OpenOptions::new().write(true).create_new(true).open(file) {
+        Ok(f) => Ok(f),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            let is_regular = fs::metadata(file).map(|m| m.is_file()).unwrap_or(false);
+            if is_regular {
+                return Err(ShellError::RedirectionError(format!(
+                    "{}: cannot overwrite existing file",
+                    file
+                )));
             }
+            OpenOptions::new()
+                .write(true)
+                .open(file)
+                .map_err(open_error)
         }
+        Err(e) => Err(open_error(e)),
+    }
+}
+
+/// Error text without Rust's " (os error N)" suffix
+fn io_message(e: &io::Error) -> String {
+    match e.kind() {
+        io::ErrorKind::NotFound => "No such file or directory".to_string(),
+        io::ErrorKind::PermissionDenied => "Permission denied".to_string(),
+        _ => e.to_string(),
+    }
+}
+
+/// Write text to an anonymous temporary file, positioned at its start
+fn text_file(text: &str) -> io::Result<File> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let path = std::env::temp_dir().join(format!(
+        "shell-heredoc-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    fs::remove_file(&path)?;
+
+    file.write_all(text.as_bytes())?;
+    file.rewind()?;
+    Ok(file)
+}
+
+/// Check whether a file descriptor is open in this process
+fn is_open(fd: RawFd) -> bool {
+    unsafe { libc::fcntl(fd, libc::F_GETFD) != -1 }
+}
+
+fn bad_descriptor(fd: RawFd) -> ShellError {
+    ShellError::RedirectionError(format!("{}: Bad file descriptor", fd))
+}
 
-        filtered_args.push(arg.clone());
-        i += 1;
+/// Duplicate `fd` to a close-on-exec descriptor numbered at least `min`
+fn dup_above(fd: RawFd, min: RawFd) -> Option<File> {
+    let copy = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, min) };
+    if copy >= 0 {
+        Some(unsafe { File::from_raw_fd(copy) })
+    } else {
+        None
     }
+}
 
-    Ok((filtered_args, stdout_redir, stderr_redir))
-}
-
-/// Set up file redirection for stdout/stderr
-pub fn setup_redirection(
-    cmd: &mut Command,
-    stdout_redir: &Option<Redirection>,
-    stderr_redir: &Option<Redirection>,
-) -> ShellResult<()> {
-    if let Some(redir) = stdout_redir {
-        let file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(matches!(redir.mode, RedirectionMode::Overwrite))
-            .append(matches!(redir.mode, RedirectionMode::Append))
-            .open(&redir.file)
-            .map_err(|e| ShellError::RedirectionError(format!("Failed to open '{}': {}", redir.file, e)))?;
-        cmd.stdout(Stdio::from(file));
+/// Set up redirections for an external command. Files are opened here, so
+/// errors are reported by the shell, and the fd operations are replayed in
+/// order in the child after its stdio (including pipes) has been set up.
+/// The command also inherits the pipes of running process substitutions
+pub fn setup_redirection(cmd: &mut Command, redirections: &Redirections) -> ShellResult<()> {
+    inherit_process_substitutions(cmd);
+    if redirections.ops.is_empty() {
+        return Ok(());
+    }
+
+    // Opened files are moved above every target fd so that applying one
+    // operation can't clobber the source of a later one
+    let max_target = redirections.ops.iter().map(|r| r.fd).max().unwrap_or(0);
+    let min_fd = max_target.max(SAVED_FD_BASE - 1) + 1;
+
+    let mut files = Vec::new();
+    // (target fd, source fd or None to close it)
+    let mut steps: Vec<(RawFd, Option<RawFd>)> = Vec::new();
+    // Whether fds touched by earlier operations are open in the child
+    let mut child_fds: HashMap<RawFd, bool> = HashMap::new();
+
+    for redirection in &redirections.ops {
+        let source = match &redirection.action {
+            RedirectionAction::Duplicate(source) => {
+                let open = child_fds
+                    .get(source)
+                    .copied()
+                    .unwrap_or_else(|| is_open(*source));
+                if !open {
+                    return Err(bad_descriptor(*source));
+                }
+                Some(*source)
+            }
+            RedirectionAction::Close => None,
+            action => {
+                let file = open_action(action)?.expect("file redirection");
+                let moved = dup_above(file.as_raw_fd(), min_fd)
+                    .ok_or_else(|| ShellError::IoError(io::Error::last_os_error()))?;
+                let fd = moved.as_raw_fd();
+                files.push(moved);
+                Some(fd)
+            }
+        };
+        child_fds.insert(redirection.fd, source.is_some());
+        steps.push((redirection.fd, source));
     }
 
-    if let Some(redir) = stderr_redir {
-        let file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(matches!(redir.mode, RedirectionMode::Overwrite))
-            .append(matches!(redir.mode, RedirectionMode::Append))
-            .open(&redir.file)
-            .map_err(|e| ShellError::RedirectionError(format!("Failed to open '{}': {}", redir.file, e)))?;
-        cmd.stderr(Stdio::from(file));
+    unsafe {
+        cmd.pre_exec(move || {
+            // The opened files stay alive until the command is spawned
+            let _files = &files;
+            for &(target, source) in &steps {
+                match source {
+                    // dup2 onto itself keeps close-on-exec set, so clear it
+                    Some(source) if source == target => {
+                        if libc::fcntl(target, libc::F_SETFD, 0) == -1 {
+                            return Err(io::Error::last_os_error());
+                        }
+                    }
+                    Some(source) => {
+                        if libc::dup2(source, target) == -1 {
+                            return Err(io::Error::last_os_error());
+                        }
+                    }
+                    None => {
+                        libc::close(target);
+                    }
+                }
+            }
+            Ok(())
+        });
     }
 
     Ok(())
 }
 
-/// Guard that restores stdout/stderr when dropped
+/// Guard that restores redirected file descriptors when dropped
 pub struct RedirectionGuard {
-    saved_stdout: Option<File>,
-    saved_stderr: Option<File>,
+    /// Original descriptors, or None for ones that were closed before
+    saved: Vec<(RawFd, Option<File>)>,
+}
+
+impl RedirectionGuard {
+    /// Remember the current state of `fd` the first time it is redirected
+    fn save(&mut self, fd: RawFd) {
+        if self.saved.iter().any(|(saved_fd, _)| *saved_fd == fd) {
+            return;
+        }
+        self.saved.push((fd, dup_above(fd, SAVED_FD_BASE)));
+    }
 }
 
 impl Drop for RedirectionGuard {
@@ -135,71 +334,57 @@ impl Drop for RedirectionGuard {
         let _ = io::Write::flush(&mut io::stdout());
         let _ = io::Write::flush(&mut io::stderr());
 
-        if let Some(saved) = self.saved_stdout.take() {
-            unsafe {
-                libc::dup2(saved.as_raw_fd(), libc::STDOUT_FILENO);
-            }
-        }
-        if let Some(saved) = self.saved_stderr.take() {
+        for (fd, saved) in self.saved.drain(..).rev() {
             unsafe {
-                libc::dup2(saved.as_raw_fd(), libc::STDERR_FILENO);
+                match saved {
+                    Some(saved) => libc::dup2(saved.as_raw_fd(), fd),
+                    None => libc::close(fd),
+                };
             }
         }
     }
 }
 
-/// Set up file redirection for builtins (redirects process stdout/stderr)
+/// Apply redirections to the shell process itself, for builtins.
 /// Returns a guard that restores the original file descriptors when dropped
-pub fn setup_builtin_redirection(
-    stdout_redir: &Option<Redirection>,
-    stderr_redir: &Option<Redirection>,
-) -> ShellResult<RedirectionGuard> {
-    let mut guard = RedirectionGuard {
-        saved_stdout: None,
-        saved_stderr: None,
-    };
+pub fn setup_builtin_redirection(redirections: &Redirections) -> ShellResult<RedirectionGuard> {
+    let mut guard = RedirectionGuard { saved: Vec::new() };
 
-    if let Some(redir) = stdout_redir {
-        // Save current stdout
-        let saved_fd = unsafe { libc::dup(libc::STDOUT_FILENO) };
-        if saved_fd >= 0 {
-            guard.saved_stdout = Some(unsafe { File::from_raw_fd(saved_fd) });
-        }
+    for redirection in &redirections.ops {
+        let fd = redirection.fd;
 
-        // Open the redirect file
-        let file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(matches!(redir.mode, RedirectionMode::Overwrite))
-            .append(matches!(redir.mode, RedirectionMode::Append))
-            .open(&redir.file)
-            .map_err(|e| ShellError::RedirectionError(format!("Failed to open '{}': {}", redir.file, e)))?;
-
-        // Redirect stdout to file
-        unsafe {
-            libc::dup2(file.as_raw_fd(), libc::STDOUT_FILENO);
-        }
-    }
+        // Pending output belongs to the original target
+        let _ = io::stdout().flush();
+        let _ = io::stderr().flush();
 
-    if let Some(redir) = stderr_redir {
-        // Save current stderr
-        let saved_fd = unsafe { libc::dup(libc::STDERR_FILENO) };
-        if saved_fd >= 0 {
-            guard.saved_stderr = Some(unsafe { File::from_raw_fd(saved_fd) });
-        }
-
-        // Open the redirect file
-        let file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(matches!(redir.mode, RedirectionMode::Overwrite))
-            .append(matches!(redir.mode, RedirectionMode::Append))
-            .open(&redir.file)
-            .map_err(|e| ShellError::RedirectionError(format!("Failed to open '{}': {}", redir.file, e)))?;
-
-        // Redirect stderr to file
-        unsafe {
-            libc::dup2(file.as_raw_fd(), libc::STDERR_FILENO);
+        match &redirection.action {
+            RedirectionAction::Duplicate(source) => {
+                if !is_open(*source) {
+                    return Err(bad_descriptor(*source));
+                }
+                guard.save(fd);
+                unsafe {
+                    libc::dup2(*source, fd);
+                }
+            }
+            RedirectionAction::Close => {
+                guard.save(fd);
+                unsafe {
+                    libc::close(fd);
+                }
+            }
+            action => {
+                let file = open_action(action)?.expect("file redirection");
+                guard.save(fd);
+                if file.as_raw_fd() == fd {
+                    // The file was opened on the target fd itself; keep it open
+                    let _ = file.into_raw_fd();
+                } else {
+                    unsafe {
+                        libc::dup2(file.as_raw_fd(), fd);
+                    }
+                }
+            }
         }
     }
 
@@ -1,80 +1,156 @@
-use crate::commands::handle_command;
 use crate::completion::ShellCompleter;
-use crate::errors::{ShellError, ShellResult};
-use crate::pipeline::{execute_pipeline, is_pipeline, split_pipeline};
-use crate::redirection::{parse_redirection, setup_builtin_redirection};
+use crate::errors::{report_error, ShellError, ShellResult};
+use crate::executor::execute_list;
+use crate::history;
+use crate::history_expansion;
+use crate::parser::{is_incomplete, parse};
+use crate::status;
+use crate::variables;
 use rustyline::config::Configurer;
 use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
 use rustyline::{ColorMode, Config, Editor};
 
-/// Read input from the user using rustyline
-pub fn read_input() -> ShellResult<String> {
-    let config = Config::builder()
-        .color_mode(ColorMode::Enabled)
-        .auto_add_history(true)
-        .build();
+/// Continuation prompt used when `PS2` is unset
+const DEFAULT_PS2: &str = "> ";
 
-    let mut rl = Editor::with_config(config).map_err(|e| {
-        ShellError::InputError(format!("Failed to create readline editor: {}", e))
-    })?;
+/// Most entries the line editor keeps for recall
+const MAX_EDITOR_HISTORY: usize = 100_000;
 
-    rl.set_helper(Some(ShellCompleter));
-    rl.set_completion_type(rustyline::CompletionType::List);
+/// Interactive state kept for the whole shell session: the line editor with
+/// its recall history, kill ring and search state
+pub struct Session {
+    editor: Editor<ShellCompleter, DefaultHistory>,
+    /// `history::generation()` when the editor's history was last rebuilt
+    history_generation: usize,
+    /// Number of `history::HISTORY` entries already copied into the editor
+    history_len: usize,
+}
+
+impl Session {
+    pub fn new() -> ShellResult<Self> {
+        let config = Config::builder()
+            .color_mode(ColorMode::Enabled)
+            // The editor mirrors `history::HISTORY`, entry for entry
+            .max_history_size(MAX_EDITOR_HISTORY)
+            .and_then(|builder| builder.history_ignore_dups(false))
+            .map_err(|e| ShellError::InputError(format!("Invalid editor configuration: {}", e)))?
+            .build();
+
+        let mut editor = Editor::with_config(config).map_err(|e| {
+            ShellError::InputError(format!("Failed to create readline editor: {}", e))
+        })?;
+        editor.set_helper(Some(ShellCompleter));
+        editor.set_completion_type(rustyline::CompletionType::List);
+
+        Ok(Self {
+            editor,
+            history_generation: history::generation(),
+            history_len: 0,
+        })
+    }
+
+    /// Read a complete command from the user
+    pub fn read_input(&mut self) -> ShellResult<String> {
+        loop {
+            self.sync_history();
 
-    loop {
-        match rl.readline("$ ") {
-            Ok(line) => {
-                let trimmed = line.trim();
-                if !trimmed.is_empty() {
-                    return Ok(trimmed.to_string());
+            match self.editor.readline("$ ") {
+                Ok(line) => {
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    // Leading blanks are kept for `HISTCONTROL=ignorespace`
+                    let line = line.trim_end().to_string();
+                    if let Some(input) = self.read_continuation(line)? {
+                        if let Some(input) = expand_history(input) {
+                            return Ok(input);
+                        }
+                    }
+                }
+                Err(ReadlineError::Interrupted) => {
+                    // Ctrl-C pressed, continue loop
+                    continue;
+                }
+                Err(ReadlineError::Eof) => {
+                    // Ctrl-D pressed, exit gracefully
+                    return Err(ShellError::Interrupted);
+                }
+                Err(e) => {
+                    return Err(ShellError::InputError(format!("Readline error: {}", e)));
                 }
-            }
-            Err(ReadlineError::Interrupted) => {
-                // Ctrl-C pressed, continue loop
-                continue;
-            }
-            Err(ReadlineError::Eof) => {
-                // Ctrl-D pressed, exit gracefully
-                return Err(ShellError::Interrupted);
-            }
-            Err(e) => {
-                return Err(ShellError::InputError(format!("Readline error: {}", e)));
             }
         }
     }
-}
 
-/// Handle a command input line
-pub fn handle_command_input(input: &str) -> ShellResult<()> {
-    // Check for pipeline
-    if is_pipeline(input) {
-        let stages: Vec<&str> = split_pipeline(input);
-        let parsed_stages: Result<Vec<Vec<String>>, _> = stages
-            .into_iter()
-            .map(|stage| shell_words::split(stage).map_err(|_| ShellError::InvalidQuoting))
-            .collect();
-        
-        return execute_pipeline(parsed_stages?);
+    /// Keep reading lines with the `PS2` prompt while the input is unfinished
+    /// (an open quote or block, a trailing `|`, a pending here-document...).
+    /// Returns None if the command was abandoned with Ctrl-C
+    fn read_continuation(&mut self, mut input: String) -> ShellResult<Option<String>> {
+        while is_incomplete(&input) {
+            let prompt = variables::get_var("PS2").unwrap_or_else(|| DEFAULT_PS2.to_string());
+            match self.editor.readline(&prompt) {
+                Ok(line) => {
+                    input.push('\n');
+                    input.push_str(&line);
+                }
+                // Ctrl-C abandons the whole command
+                Err(ReadlineError::Interrupted) => return Ok(None),
+                // Ctrl-D: run what we have and let the parser report the problem
+                Err(ReadlineError::Eof) => break,
+                Err(e) => {
+                    return Err(ShellError::InputError(format!("Readline error: {}", e)));
+                }
+            }
+        }
+        Ok(Some(input))
     }
 
-    // Parse the command using shell-words
-    let args = shell_words::split(input)
-        .map_err(|_| ShellError::InvalidQuoting)?;
-
-    if args.is_empty() {
-        return Ok(());
-    }
+    /// Bring the editor's recall history in line with `history::HISTORY`:
+    /// copy new entries, or reload everything after the list was replaced
+    /// (e.g. by `history -r`)
+    fn sync_history(&mut self) {
+        let entries = history::get_history();
+        let generation = history::generation();
 
-    // Parse redirections first (for both builtins and external commands)
-    let (filtered_args, stdout_redir, stderr_redir) = parse_redirection(&args)?;
+        if generation != self.history_generation || entries.len() < self.history_len {
+            let _ = self.editor.clear_history();
+            self.history_generation = generation;
+            self.history_len = 0;
+        }
 
-    if filtered_args.is_empty() {
-        return Ok(());
+        for entry in &entries[self.history_len..] {
+            let _ = self.editor.add_history_entry(entry.as_str());
+        }
+        self.history_len = entries.len();
     }
+}
 
-    // Set up redirection for builtins (will be restored when guard is dropped)
-    let _guard = setup_builtin_redirection(&stdout_redir, &stderr_redir)?;
+/// Apply history expansion to a complete command, echoing the result when
+/// it changed. Returns None if the command shouldn't run: the expansion
+/// failed, or `:p` asked for it to be printed only
+fn expand_history(input: String) -> Option<String> {
+    match history_expansion::expand(&input, &history::get_history()) {
+        Ok(expansion) if expansion.expanded => {
+            println!("{}", expansion.line);
+            if expansion.print_only {
+                history::add_to_history(&expansion.line);
+                return None;
+            }
+            Some(expansion.line)
+        }
+        Ok(_) => Some(input),
+        Err(e) => {
+            report_error(&e);
+            status::set_last_status(e.exit_status());
+            None
+        }
+    }
+}
 
-    // Execute command (handles both builtins and external)
-    handle_command(&filtered_args)
-}
\ No newline at end of file
+/// Handle a command input line
+pub fn handle_command_input(input: &str) -> ShellResult<()> {
+    let list = parse(input)?;
+    execute_list(&list)?;
+    Ok(())
+}
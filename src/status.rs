@@ -0,0 +1,31 @@
+use std::sync::atomic::{AtomicI32, Ordering};
+
+/// Exit status of the most recently executed pipeline (`$?`)
+static LAST_STATUS: AtomicI32 = AtomicI32::new(0);
+
+/// Get the exit status of the last command
+pub fn last_status() -> i32 {
+    LAST_STATUS.load(Ordering::SeqCst)
+}
+
+/// Record the exit status of the last command
+pub fn set_last_status(status: i32) {
+    LAST_STATUS.store(status, Ordering::SeqCst);
+}
+
+/// Status of the most recent command substitution, or -1 if none ran since
+/// the last reset. An assignment-only command reports this as its status
+static SUBSTITUTION_STATUS: AtomicI32 = AtomicI32::new(-1);
+
+/// Record the exit status of a command substitution
+pub fn set_substitution_status(status: i32) {
+    SUBSTITUTION_STATUS.store(status, Ordering::SeqCst);
+}
+
+/// Take the status of the last command substitution, resetting it
+pub fn take_substitution_status() -> Option<i32> {
+    match SUBSTITUTION_STATUS.swap(-1, Ordering::SeqCst) {
+        -1 => None,
+        status => Some(status),
+    }
+}
@@ -0,0 +1,270 @@
+use once_cell::sync::Lazy;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::sync::Mutex;
+
+/// A shell variable and its attributes
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variable {
+    pub value: String,
+    /// Exported variables are passed in the environment of child processes
+    pub exported: bool,
+}
+
+/// Table of shell variables
+#[derive(Debug, Default)]
+pub struct VariableStore {
+    vars: HashMap<String, Variable>,
+    /// Names exported before they had a value (`export NAME`). They stay
+    /// unset, and are exported once they are assigned
+    pending_exports: HashSet<String>,
+}
+
+impl VariableStore {
+    /// Create a store pre-populated with the process environment
+    pub fn from_env() -> Self {
+        Self {
+            vars: env::vars()
+                .map(|(name, value)| {
+                    let var = Variable {
+                        value,
+                        exported: true,
+                    };
+                    (name, var)
+                })
+                .collect(),
+            pending_exports: HashSet::new(),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.vars.get(name).map(|v| v.value.as_str())
+    }
+
+    pub fn get_variable(&self, name: &str) -> Option<&Variable> {
+        self.vars.get(name)
+    }
+
+    /// Set a variable's value, keeping its export attribute
+    pub fn set(&mut self, name: &str, value: &str) {
+        match self.vars.get_mut(name) {
+            Some(var) => var.value = value.to_string(),
+            None => {
+                self.vars.insert(
+                    name.to_string(),
+                    Variable {
+                        value: value.to_string(),
+                        exported: self.pending_exports.contains(name),
+                    },
+                );
+            }
+        }
+    }
+
+    /// Replace a variable (value and attributes) or remove it with `None`
+    pub fn set_variable(&mut self, name: &str, var: Option<Variable>) {
+        match var {
+            Some(var) => {
+                self.vars.insert(name.to_string(), var);
+            }
+            None => {
+                self.vars.remove(name);
+            }
+        }
+    }
+
+    /// Mark a variable as exported (or not). An unset variable stays unset
+    /// until it is assigned
+    pub fn set_exported(&mut self, name: &str, exported: bool) {
+        if let Some(var) = self.vars.get_mut(name) {
+            var.exported = exported;
+        }
+        if exported && !self.vars.contains_key(name) {
+            self.pending_exports.insert(name.to_string());
+        } else if !exported {
+            self.pending_exports.remove(name);
+        }
+    }
+
+    pub fn unset(&mut self, name: &str) -> Option<String> {
+        self.pending_exports.remove(name);
+        self.vars.remove(name).map(|v| v.value)
+    }
+
+    /// Names marked for export that have no value, sorted
+    pub fn pending_exports(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .pending_exports
+            .iter()
+            .filter(|name| !self.vars.contains_key(*name))
+            .cloned()
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// All exported variables as (name, value) pairs, sorted by name
+    pub fn exported(&self) -> Vec<(String, String)> {
+        let mut vars: Vec<(String, String)> = self
+            .vars
+            .iter()
+            .filter(|(_, v)| v.exported)
+            .map(|(name, v)| (name.clone(), v.value.clone()))
+            .collect();
+        vars.sort();
+        vars
+    }
+
+    /// All variables as (name, value) pairs, sorted by name
+    pub fn all(&self) -> Vec<(String, String)> {
+        let mut vars: Vec<(String, String)> = self
+            .vars
+            .iter()
+            .map(|(name, v)| (name.clone(), v.value.clone()))
+            .collect();
+        vars.sort();
+        vars
+    }
+}
+
+/// Shared shell variable state
+pub static VARIABLES: Lazy<Mutex<VariableStore>> =
+    Lazy::new(|| Mutex::new(VariableStore::from_env()));
+
+/// Get the value of a shell variable
+pub fn get_var(name: &str) -> Option<String> {
+    VARIABLES
+        .lock()
+        .ok()
+        .and_then(|vars| vars.get(name).map(|s| s.to_string()))
+}
+
+/// Set a shell variable
+pub fn set_var(name: &str, value: &str) {
+    if let Ok(mut vars) = VARIABLES.lock() {
+        vars.set(name, value);
+    }
+}
+
+/// Get a shell variable together with its attributes
+pub fn get_variable(name: &str) -> Option<Variable> {
+    VARIABLES
+        .lock()
+        .ok()
+        .and_then(|vars| vars.get_variable(name).cloned())
+}
+
+/// Replace a shell variable (value and attributes), or remove it with `None`
+pub fn set_variable(name: &str, var: Option<Variable>) {
+    if let Ok(mut vars) = VARIABLES.lock() {
+        vars.set_variable(name, var);
+    }
+}
+
+/// Mark a shell variable as exported or local
+pub fn set_exported(name: &str, exported: bool) {
+    if let Ok(mut vars) = VARIABLES.lock() {
+        vars.set_exported(name, exported);
+    }
+}
+
+/// Environment passed to child processes
+pub fn exported_vars() -> Vec<(String, String)> {
+    VARIABLES
+        .lock()
+        .map(|vars| vars.exported())
+        .unwrap_or_default()
+}
+
+/// Names marked for export that have no value yet
+pub fn pending_exports() -> Vec<String> {
+    VARIABLES
+        .lock()
+        .map(|vars| vars.pending_exports())
+        .unwrap_or_default()
+}
+
+/// Every shell variable, exported or not
+pub fn all_vars() -> Vec<(String, String)> {
+    VARIABLES.lock().map(|vars| vars.all()).unwrap_or_default()
+}
+
+/// Remove a shell variable, returning its previous value
+pub fn unset_var(name: &str) -> Option<String> {
+    VARIABLES.lock().ok().and_then(|mut vars| vars.unset(name))
+}
+
+/// Positional parameters (`$1`, `$2`, ...) of the running function
+static POSITIONAL: Lazy<Mutex<Vec<String>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+/// Get the positional parameters
+pub fn positional_params() -> Vec<String> {
+    POSITIONAL
+        .lock()
+        .map(|params| params.clone())
+        .unwrap_or_default()
+}
+
+/// Replace the positional parameters, returning the previous ones
+pub fn set_positional_params(params: Vec<String>) -> Vec<String> {
+    POSITIONAL
+        .lock()
+        .map(|mut current| std::mem::replace(&mut *current, params))
+        .unwrap_or_default()
+}
+
+/// Variables made local in one function call, with the values they had before
+type LocalScope = Vec<(String, Option<Variable>)>;
+
+/// Scopes of the active function calls, innermost last
+static LOCAL_SCOPES: Lazy<Mutex<Vec<LocalScope>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+/// Start a new scope for `local` variables
+pub fn push_local_scope() {
+    if let Ok(mut scopes) = LOCAL_SCOPES.lock() {
+        scopes.push(Vec::new());
+    }
+}
+
+/// End the innermost scope, restoring the variables it shadowed
+pub fn pop_local_scope() {
+    let saved = LOCAL_SCOPES
+        .lock()
+        .ok()
+        .and_then(|mut scopes| scopes.pop())
+        .unwrap_or_default();
+    for (name, previous) in saved.into_iter().rev() {
+        set_variable(&name, previous);
+    }
+}
+
+/// Make a variable local to the innermost scope, starting out unset.
+/// Returns false if no function is running
+pub fn make_local(name: &str) -> bool {
+    let previous = get_variable(name);
+    let Ok(mut scopes) = LOCAL_SCOPES.lock() else {
+        return false;
+    };
+    let Some(scope) = scopes.last_mut() else {
+        return false;
+    };
+
+    // Only the value from outside the function is worth restoring
+    if !scope.iter().any(|(saved, _)| saved == name) {
+        scope.push((name.to_string(), previous));
+        drop(scopes);
+        unset_var(name);
+    }
+    true
+}
+
+/// Check whether a string is a valid variable name
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}